@@ -3,8 +3,8 @@ class ServerConfig:
     Configuration class for the server.
 
     This class contains a collection of setters for configuring a Reduce server.
-    The settings are write only, and can only be set once before starting the
-    server.
+    The settings are write only, and only apply to servers started after they
+    were set. Setters raise a `ValueError` for values that could never work.
     """
     def __init__(self):
         """
@@ -39,11 +39,203 @@ class ServerConfig:
         """
         ...
 
-    def start_server(self):
+    def dev_mode(self, value: bool):
         """
-        Start the Reduce server.
+        Run the server for local development over plain HTTP.
+
+        Cookies are sent without `Secure` and every page shows a warning
+        banner. Never use this for a server others can reach.
+        """
+        ...
+
+    def database_max_connections(self, value: int):
+        """
+        Set how many connections to the database the server keeps open at most.
+        Defaults to 10.
+        """
+        ...
+
+    def enabled_modules(self, value: list[str]):
+        """
+        Set which modules the server offers, any of `upkeep`, `focus` and
+        `caldav`. All of them are enabled by default. Logging in and the
+        account page are always available.
+        """
+        ...
+
+    def bootstrap_secret(self, value: str | None):
+        """
+        Set the secret that creates the first account, see the bootstrapping
+        guide. Without one, no account can be bootstrapped. Remove it once the
+        server has its accounts.
+        """
+        ...
+
+    def log_level(self, value: str):
+        """
+        Set the most detailed level that is logged, one of `trace`, `debug`,
+        `info`, `warn` and `error`. Defaults to `trace`.
+        """
+        ...
+
+    def log_directory(self, value: str | None):
+        """
+        Write logs as JSON to a new file in this directory every day, instead
+        of as text to the terminal.
+
+        .. IMPORTANT::
+           Logging is set up by the first server started in a process, later
+           servers keep it.
+        """
+        ...
+
+    def session_lifetime(self, seconds: float):
+        """
+        Set how long a login lasts, both on the server and in the browser.
+        Defaults to one day.
+        """
+        ...
+
+    def session_cookie_name(self, value: str):
+        """
+        Set the name of the session cookie. Defaults to `session_token`.
+        """
+        ...
+
+    def cookie_same_site(self, value: str):
+        """
+        Set the `SameSite` attribute of every cookie, one of `strict`, `lax`
+        and `none`. Defaults to `lax`. `none` does not work in development
+        mode.
+        """
+        ...
+
+    def cookie_domain(self, value: str | None):
+        """
+        Set the `Domain` attribute of every cookie, which shares them with all
+        subdomains. Without it, only the exact host of the server gets them.
+        """
+        ...
+
+    def cookie_host_prefix(self, value: bool):
+        """
+        Prefix every cookie name with `__Host-`, browsers then only accept
+        them over HTTPS, for the whole site and without a domain. Can't be
+        combined with :meth:`cookie_domain`.
+        """
+        ...
+
+    def hsts_max_age(self, seconds: int | None):
+        """
+        Set how long browsers insist on HTTPS for this server, sent as
+        `Strict-Transport-Security`. Defaults to a year, `None` leaves the
+        header out.
+        """
+        ...
+
+    def frame_options(self, value: str):
+        """
+        Set the `X-Frame-Options` header. Defaults to `DENY`.
+        """
+        ...
+
+    def referrer_policy(self, value: str):
+        """
+        Set the `Referrer-Policy` header. Defaults to `same-origin`.
+        """
+        ...
+
+    def permissions_policy(self, value: str):
+        """
+        Set the `Permissions-Policy` header. By default the camera,
+        microphone, location, payments and USB are turned off.
+        """
+        ...
+
+    def script_sources(self, value: list[str]):
+        """
+        Allow scripts from these sources as well, next to the server itself.
+        Each one is added to `script-src` in the content security policy.
+        """
+        ...
+
+    def content_security_policy_report_only(self, value: bool):
+        """
+        Only report violations of the content security policy to the browser
+        console, without blocking anything.
+        """
+        ...
+
+    def tls(
+        self,
+        certificate_path: str,
+        key_path: str,
+        reload_interval: float = 60.0,
+        redirect_bind_address: str | None = None,
+    ):
+        """
+        Serve HTTPS, instead of relying on a proxy in front of the server.
+
+        The certificate is a PEM file, with the intermediate certificates after
+        the certificate itself. Both files are checked every `reload_interval`
+        seconds and a renewed certificate is loaded without dropping
+        connections. A SIGHUP reloads them right away.
+
+        With `redirect_bind_address`, the server also listens for plain HTTP
+        there, only to redirect to HTTPS.
+        """
+        ...
+
+    def start_server(self) -> ServerHandle:
+        """
+        Start the Reduce server in the background.
+
+        Raises a `ValueError` for settings that don't work together, like
+        `SameSite=None` cookies in development mode.
 
         .. IMPORTANT::
-           The lifetime of the server is tied to the instance of self.
+           The lifetime of the server is tied to the returned handle, keep it
+           around for as long as the server should run.
+        """
+        ...
+
+class ServerHandle:
+    """
+    A running Reduce server, returned by :meth:`ServerConfig.start_server`.
+
+    Use it as a context manager to stop the server when the block is left:
+
+    .. code-block:: python
+
+        with config.start_server() as server:
+            server.wait()
+    """
+    def is_running(self) -> bool:
+        """
+        Whether the server is still running. It stops on its own if it fails,
+        for example when the bind address is already in use.
+        """
+        ...
+
+    def wait(self, timeout: float | None = None) -> bool:
+        """
+        Block until the server stops, or until `timeout` seconds have passed.
+
+        Returns whether the server stopped. Raises a `RuntimeError` if it
+        stopped because of an error. Ctrl+C still interrupts the wait.
         """
         ...
+
+    def stop(self, timeout: float | None = None) -> bool:
+        """
+        Stop accepting connections and let open requests finish, then close
+        the database connections.
+
+        Requests still open after `timeout` seconds are dropped. Returns
+        whether all of them finished in time. Without a timeout this waits for
+        as long as they take.
+        """
+        ...
+
+    def __enter__(self) -> ServerHandle: ...
+    def __exit__(self, exc_type, exc_value, traceback) -> bool: ...
@@ -1,68 +1,397 @@
-use pyo3::{exceptions::PyRuntimeError, prelude::*};
-use reduce_core::ServerConfig as CoreConfig;
-use tokio::runtime::Runtime;
+use std::{
+    path::PathBuf,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
 
-#[allow(dead_code)]
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+use reduce_core::{Level, Module, SameSite, ServerConfig as CoreConfig, TlsConfig};
+use tokio::{runtime::Runtime, sync::oneshot, task::JoinHandle};
+
+/// How long `wait` blocks at a time, so Ctrl+C still gets through to Python.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tracing is global, a server started again after stopping keeps what the first one set up.
+static TRACING: OnceLock<Result<(), String>> = OnceLock::new();
+
+fn timeout_duration(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    timeout
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds).map_err(|_| {
+                PyErr::new::<PyValueError, _>("The timeout can not be a negative number of seconds")
+            })
+        })
+        .transpose()
+}
+
+/// A running server. It is stopped when this is dropped, without waiting for open requests.
 #[pyclass]
-struct ServerRuntimeHandle(Runtime);
+struct ServerHandle {
+    runtime: Option<Runtime>,
+    shutdown: Option<oneshot::Sender<()>>,
+    server: Option<JoinHandle<Result<(), String>>>,
+    error: Option<String>,
+}
+
+impl ServerHandle {
+    /// Waits for the server to finish, at most until `deadline`. Returns whether it did.
+    fn join(&mut self, py: Python<'_>, deadline: Option<Instant>) -> PyResult<bool> {
+        let (Some(runtime), Some(server)) = (&self.runtime, &mut self.server) else {
+            return Ok(true);
+        };
+        let result = py.allow_threads(|| {
+            runtime.block_on(async {
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline.into(), server).await.ok(),
+                    None => Some(server.await),
+                }
+            })
+        });
+        match result {
+            Some(result) => {
+                self.server = None;
+                self.error = match result {
+                    Ok(Ok(())) => None,
+                    Ok(Err(error)) => Some(error),
+                    Err(error) => Some(error.to_string()),
+                };
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn raise_error(&self) -> PyResult<()> {
+        match &self.error {
+            Some(error) => Err(PyErr::new::<PyRuntimeError, _>(error.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl ServerHandle {
+    fn is_running(&self) -> bool {
+        self.server
+            .as_ref()
+            .is_some_and(|server| !server.is_finished())
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn wait(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let deadline = timeout_duration(timeout)?.map(|timeout| Instant::now() + timeout);
+        loop {
+            let next_check = Instant::now() + SIGNAL_CHECK_INTERVAL;
+            let step = match deadline {
+                Some(deadline) => deadline.min(next_check),
+                None => next_check,
+            };
+            if self.join(py, Some(step))? {
+                self.raise_error()?;
+                return Ok(true);
+            }
+            py.check_signals()?;
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(false);
+            }
+        }
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn stop(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let deadline = timeout_duration(timeout)?.map(|timeout| Instant::now() + timeout);
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        let drained = self.join(py, deadline)?;
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+        Ok(drained)
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<bool> {
+        self.stop(py, None)?;
+        Ok(false)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> PyErr {
+    PyErr::new::<PyValueError, _>(message.into())
+}
+
+fn positive_duration(seconds: f64, name: &str) -> PyResult<Duration> {
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(invalid(format!(
+            "The {} has to be a positive number of seconds",
+            name
+        ))),
+    }
+}
+
+fn bind_address(value: String) -> PyResult<Box<str>> {
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(value.into()),
+        _ => Err(invalid(format!(
+            "{:?} is not an address to bind to, expected {{ip}}:{{port}}",
+            value
+        ))),
+    }
+}
+
+/// Anything else would be rejected when it's sent as a header.
+fn header_value(value: String, name: &str) -> PyResult<Box<str>> {
+    match value.bytes().all(|byte| (0x20..0x7f).contains(&byte)) {
+        true => Ok(value.into()),
+        false => Err(invalid(format!(
+            "The {} can only contain printable ASCII characters",
+            name
+        ))),
+    }
+}
 
 #[pyclass]
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct ServerConfig {
-    database_url: Option<String>,
-    server_bind_address: Option<String>,
-    runtime: Option<Runtime>,
+    database_url: Option<Box<str>>,
+    server_bind_address: Option<Box<str>>,
+    /// Every other setting, the two above are filled in once the server starts.
+    settings: CoreConfig,
 }
 
 #[pymethods]
 impl ServerConfig {
     #[new]
     fn new() -> ServerConfig {
-        ServerConfig::default()
+        ServerConfig {
+            database_url: None,
+            server_bind_address: None,
+            settings: CoreConfig::new("", ""),
+        }
+    }
+
+    fn database_url(&mut self, value: String) -> PyResult<()> {
+        if !value.starts_with("postgres://") && !value.starts_with("postgresql://") {
+            return Err(invalid("The database URL has to start with postgres://"));
+        }
+        self.database_url = Some(value.into());
+        Ok(())
     }
 
-    fn database_url(&mut self, value: String) {
-        self.database_url = Some(value);
+    fn server_bind_address(&mut self, value: String) -> PyResult<()> {
+        self.server_bind_address = Some(bind_address(value)?);
+        Ok(())
     }
 
-    fn server_bind_address(&mut self, value: String) {
-        self.server_bind_address = Some(value);
+    fn dev_mode(&mut self, value: bool) {
+        self.settings.dev_mode = value;
     }
 
-    fn start_server(&mut self) -> PyResult<()> {
-        reduce_core::setup_tracing()
-            .map_err(|error| PyErr::new::<PyRuntimeError, _>(error.to_string()))?;
-        let runtime = Runtime::new()?;
-        let config = match self {
-            ServerConfig {
-                database_url: Some(database_url),
-                server_bind_address: Some(server_bind_address),
-                ..
-            } => CoreConfig {
-                db_url: database_url.as_str().into(),
-                server_bind_address: server_bind_address.as_str().into(),
-            },
+    fn database_max_connections(&mut self, value: u32) -> PyResult<()> {
+        if value == 0 {
+            return Err(invalid("The server needs at least one database connection"));
+        }
+        self.settings.max_database_connections = value;
+        Ok(())
+    }
+
+    fn enabled_modules(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.settings.modules = value
+            .iter()
+            .map(|name| name.parse::<Module>())
+            .collect::<Result<_, _>>()
+            .map_err(|error| invalid(error.to_string()))?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (value))]
+    fn bootstrap_secret(&mut self, value: Option<String>) -> PyResult<()> {
+        if value.as_deref().is_some_and(str::is_empty) {
+            return Err(invalid("The bootstrap secret can't be empty"));
+        }
+        self.settings.bootstrap_secret = value.map(Box::from);
+        Ok(())
+    }
+
+    fn log_level(&mut self, value: String) -> PyResult<()> {
+        self.settings.log.max_level = value.parse::<Level>().map_err(|_| {
+            invalid(format!(
+                "{:?} is not a log level, expected trace, debug, info, warn or error",
+                value
+            ))
+        })?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (value))]
+    fn log_directory(&mut self, value: Option<PathBuf>) {
+        self.settings.log.directory = value;
+    }
+
+    fn session_lifetime(&mut self, seconds: f64) -> PyResult<()> {
+        self.settings.cookies.session_lifetime = positive_duration(seconds, "session lifetime")?;
+        Ok(())
+    }
+
+    fn session_cookie_name(&mut self, value: String) -> PyResult<()> {
+        if value.is_empty()
+            || !value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(invalid(
+                "A cookie name can only contain letters, digits, - and _",
+            ));
+        }
+        self.settings.cookies.session_name = value.into();
+        Ok(())
+    }
+
+    fn cookie_same_site(&mut self, value: String) -> PyResult<()> {
+        self.settings.cookies.same_site = match value.to_ascii_lowercase().as_str() {
+            "strict" => SameSite::Strict,
+            "lax" => SameSite::Lax,
+            "none" => SameSite::None,
             _ => {
-                return Err(PyErr::new::<PyRuntimeError, _>(
-                    "Could not create server because of missing configuration",
-                ))
+                return Err(invalid(format!(
+                    "{:?} is not a SameSite value, expected strict, lax or none",
+                    value
+                )))
             }
         };
-        runtime.spawn(async move {
-            if let Err(e) = reduce_core::start_server(config).await {
-                eprintln!("Error starting server: {}", e);
-            }
-        });
+        Ok(())
+    }
+
+    #[pyo3(signature = (value))]
+    fn cookie_domain(&mut self, value: Option<String>) -> PyResult<()> {
+        self.settings.cookies.domain = value
+            .map(|domain| header_value(domain, "cookie domain"))
+            .transpose()?;
+        Ok(())
+    }
 
-        self.runtime = Some(runtime);
+    fn cookie_host_prefix(&mut self, value: bool) {
+        self.settings.cookies.host_prefix = value;
+    }
 
+    #[pyo3(signature = (seconds))]
+    fn hsts_max_age(&mut self, seconds: Option<u64>) {
+        self.settings.security_headers.hsts_max_age_seconds = seconds;
+    }
+
+    fn frame_options(&mut self, value: String) -> PyResult<()> {
+        self.settings.security_headers.frame_options = header_value(value, "frame options")?;
+        Ok(())
+    }
+
+    fn referrer_policy(&mut self, value: String) -> PyResult<()> {
+        self.settings.security_headers.referrer_policy = header_value(value, "referrer policy")?;
+        Ok(())
+    }
+
+    fn permissions_policy(&mut self, value: String) -> PyResult<()> {
+        self.settings.security_headers.permissions_policy =
+            header_value(value, "permissions policy")?;
+        Ok(())
+    }
+
+    fn script_sources(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.settings.security_headers.script_sources = value
+            .into_iter()
+            .map(|source| {
+                if source.is_empty() || source.contains(|c: char| c == ';' || c.is_whitespace()) {
+                    return Err(invalid(format!("{:?} is not a script source", source)));
+                }
+                header_value(source, "script source")
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(())
+    }
+
+    fn content_security_policy_report_only(&mut self, value: bool) {
+        self.settings.security_headers.report_only = value;
+    }
+
+    #[pyo3(signature = (certificate_path, key_path, reload_interval=60.0, redirect_bind_address=None))]
+    fn tls(
+        &mut self,
+        certificate_path: PathBuf,
+        key_path: PathBuf,
+        reload_interval: f64,
+        redirect_bind_address: Option<String>,
+    ) -> PyResult<()> {
+        let mut tls = TlsConfig::new(certificate_path, key_path);
+        tls.reload_interval = positive_duration(reload_interval, "reload interval")?;
+        tls.redirect_bind_address = redirect_bind_address.map(bind_address).transpose()?;
+        self.settings.tls = Some(tls);
         Ok(())
     }
+
+    fn start_server(&self) -> PyResult<ServerHandle> {
+        let (Some(database_url), Some(server_bind_address)) =
+            (&self.database_url, &self.server_bind_address)
+        else {
+            return Err(PyErr::new::<PyRuntimeError, _>(
+                "Could not create server because of missing configuration",
+            ));
+        };
+        let config = CoreConfig {
+            db_url: database_url.clone(),
+            server_bind_address: server_bind_address.clone(),
+            ..self.settings.clone()
+        };
+        config
+            .cookies
+            .validate(config.dev_mode)
+            .map_err(|error| invalid(error.to_string()))?;
+
+        TRACING
+            .get_or_init(|| {
+                reduce_core::setup_tracing(&config.log).map_err(|error| error.to_string())
+            })
+            .clone()
+            .map_err(PyErr::new::<PyRuntimeError, _>)?;
+        let runtime = Runtime::new()?;
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let server = runtime.spawn(async move {
+            let stopped = async {
+                let _ = stopped.await;
+            };
+            reduce_core::start_server(config, stopped)
+                .await
+                .map_err(|error| format!("Error running server: {}", error))
+        });
+
+        Ok(ServerHandle {
+            runtime: Some(runtime),
+            shutdown: Some(shutdown),
+            server: Some(server),
+            error: None,
+        })
+    }
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn pyreduce(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ServerConfig>()?;
+    m.add_class::<ServerHandle>()?;
     Ok(())
 }
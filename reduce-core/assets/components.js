@@ -0,0 +1,52 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// The CSP build of Alpine can't evaluate expressions in attributes, so every piece of state
+// the templates use is registered here, and the attributes only name it.
+document.addEventListener("alpine:init", () => {
+  // Something that opens and closes, like a menu or a list that is hidden at first. It starts
+  // open with a `data-open` attribute, to show a form that has to be corrected.
+  Alpine.data("toggle", () => ({
+    open: false,
+    init() {
+      this.open = "open" in this.$el.dataset;
+    },
+    toggle() {
+      this.open = !this.open;
+    },
+    close() {
+      this.open = false;
+    },
+  }));
+
+  // A message that is shown until it's dismissed.
+  Alpine.data("dismissible", () => ({
+    open: true,
+    dismiss() {
+      this.open = false;
+    },
+  }));
+
+  // The full URL of a path on this server, from the `data-path` attribute.
+  Alpine.data("absoluteUrl", () => ({
+    url: "",
+    init() {
+      this.url = window.location.origin + this.$el.dataset.path;
+    },
+  }));
+});
@@ -0,0 +1,204 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Embeds the static assets into the binary. Every file gets a URL with a hash of its content, so
+//! it can be cached forever. Compressed variants are picked up from `.gz` and `.br` files next to
+//! the original, they're made by `compress-assets.js` and never here.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Tracked assets, and the output of tailwind.
+const ROOTS: [&str; 2] = ["assets", "static"];
+
+/// The templates link to assets with `asset_url("...")`.
+const TEMPLATES: &str = "templates";
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if is_hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, files);
+        } else if !matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("gz" | "br")
+        ) {
+            files.push(path);
+        }
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        Some("woff") => "font/woff",
+        Some("ttf") => "font/ttf",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `vendor/htmx.min.js` becomes `vendor/htmx.min.{hash}.js`.
+fn hashed_path(path: &str, hash: &str) -> String {
+    let name_start = path.rfind('/').map_or(0, |index| index + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) => {
+            let dot = name_start + dot;
+            format!("{}.{}{}", &path[..dot], hash, &path[dot..])
+        }
+        None => format!("{}.{}", path, hash),
+    }
+}
+
+/// The paths passed to `asset_url` as a string literal, in every file below `dir`.
+fn collect_references(dir: &Path, references: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_references(&path, references);
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        for (index, call) in content.match_indices("asset_url(\"") {
+            let rest = &content[index + call.len()..];
+            if let Some(end) = rest.find('"') {
+                references.push((rest[..end].to_string(), path.clone()));
+            }
+        }
+    }
+}
+
+fn variant(path: &Path, extension: &str) -> String {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".");
+    compressed.push(extension);
+    let compressed = PathBuf::from(compressed);
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(path), modified(&compressed)) {
+        (Ok(original), Ok(variant)) if variant >= original => {
+            format!("Some(include_bytes!({:?}))", compressed)
+        }
+        // A variant of an older version would serve the old content under the new hash.
+        (_, Ok(_)) => {
+            println!(
+                "cargo:warning={} is older than its asset and left out, run `pnpm run \
+                reduce-core:compress` to update it",
+                compressed.display()
+            );
+            "None".to_string()
+        }
+        _ => "None".to_string(),
+    }
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let mut seen = Vec::new();
+    let mut output = String::from("pub static ASSETS: &[Asset] = &[\n");
+
+    for root in ROOTS {
+        let root = manifest_dir.join(root);
+        println!("cargo:rerun-if-changed={}", root.display());
+
+        let mut files = Vec::new();
+        collect(&root, &mut files);
+        files.sort();
+        for file in files {
+            let relative = file
+                .strip_prefix(&root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            if seen.contains(&relative) {
+                println!(
+                    "cargo:warning=The asset {} exists twice, only the first is used",
+                    relative
+                );
+                continue;
+            }
+
+            let body = fs::read(&file).unwrap();
+            let hash: String = Sha256::digest(&body)[..8]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            writeln!(
+                output,
+                "    Asset {{ path: {:?}, hashed_path: {:?}, content_type: {:?}, hash: {:?}, \
+                body: include_bytes!({:?}), gzip: {}, brotli: {} }},",
+                relative,
+                hashed_path(&relative, &hash),
+                content_type(&file),
+                hash,
+                file,
+                variant(&file, "gz"),
+                variant(&file, "br"),
+            )
+            .unwrap();
+            seen.push(relative);
+        }
+    }
+
+    // Vendored scripts and the tailwind output aren't tracked, a fresh clone has to build them
+    // first. Without this, the pages would load but link to assets that don't exist.
+    let mut references = Vec::new();
+    let templates = manifest_dir.join(TEMPLATES);
+    println!("cargo:rerun-if-changed={}", templates.display());
+    collect_references(&templates, &mut references);
+    let missing: Vec<_> = references
+        .iter()
+        .filter(|(path, _)| !seen.contains(path))
+        .map(|(path, file)| format!("{} (used in {})", path, file.display()))
+        .collect();
+    if !missing.is_empty() {
+        panic!(
+            "These assets are missing: {}. Run `pnpm install` and `pnpm run reduce-core:assets` \
+            in the root of the repository to build them.",
+            missing.join(", ")
+        );
+    }
+
+    output.push_str("];\n");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("assets.rs"), output).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,59 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Writes a gzip and a brotli variant next to every asset that compresses well. The build script
+// embeds them and serves whichever the browser accepts, variants older than their asset are left
+// out until this runs again.
+const fs = require("node:fs");
+const path = require("node:path");
+const zlib = require("node:zlib");
+
+const ROOTS = ["assets", "static"].map((root) => path.join(__dirname, root));
+const COMPRESSIBLE = [".css", ".js", ".json", ".map", ".svg", ".txt"];
+
+function compress(file) {
+  const body = fs.readFileSync(file);
+  fs.writeFileSync(`${file}.gz`, zlib.gzipSync(body, { level: 9 }));
+  fs.writeFileSync(
+    `${file}.br`,
+    zlib.brotliCompressSync(body, {
+      params: {
+        [zlib.constants.BROTLI_PARAM_QUALITY]: zlib.constants.BROTLI_MAX_QUALITY,
+        [zlib.constants.BROTLI_PARAM_SIZE_HINT]: body.length,
+      },
+    }),
+  );
+}
+
+function walk(dir) {
+  if (!fs.existsSync(dir)) {
+    return;
+  }
+  for (const entry of fs.readdirSync(dir, { withFileTypes: true })) {
+    const file = path.join(dir, entry.name);
+    if (entry.name.startsWith(".")) {
+      continue;
+    } else if (entry.isDirectory()) {
+      walk(file);
+    } else if (COMPRESSIBLE.includes(path.extname(entry.name))) {
+      compress(file);
+    }
+  }
+}
+
+ROOTS.forEach(walk);
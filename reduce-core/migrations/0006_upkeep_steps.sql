@@ -0,0 +1,27 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+CREATE TABLE upkeep_steps (
+  id SERIAL PRIMARY KEY,
+  upkeep_item_id INT NOT NULL REFERENCES upkeep_items(id) ON DELETE CASCADE,
+  position INT NOT NULL,
+  description VARCHAR(255) NOT NULL,
+  checked BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX upkeep_steps_item_index ON upkeep_steps (upkeep_item_id, position);
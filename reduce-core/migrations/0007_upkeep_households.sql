@@ -0,0 +1,49 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+CREATE TABLE households (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE household_members (
+  id SERIAL PRIMARY KEY,
+  household_id INT NOT NULL REFERENCES households(id) ON DELETE CASCADE,
+  account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  UNIQUE (household_id, account_id)
+);
+
+-- A shared item stays owned by the account that created it, so it falls back to being a
+-- personal item when its household goes away.
+ALTER TABLE upkeep_items
+  ADD household_id INT REFERENCES households(id) ON DELETE SET NULL,
+  ADD assignment_mode VARCHAR(16) NOT NULL DEFAULT 'rotate'
+    CHECK (assignment_mode IN ('rotate', 'completer')),
+  ADD assigned_account_id INT REFERENCES accounts(id) ON DELETE SET NULL;
+
+ALTER TABLE upkeep_completions
+  ADD account_id INT REFERENCES accounts(id) ON DELETE SET NULL;
+
+CREATE VIEW upkeep_item_access AS
+  SELECT id AS upkeep_item_id, account_id
+  FROM upkeep_items
+  WHERE household_id IS NULL
+  UNION ALL
+  SELECT upkeep_items.id AS upkeep_item_id, household_members.account_id
+  FROM upkeep_items
+  JOIN household_members ON household_members.household_id = upkeep_items.household_id;
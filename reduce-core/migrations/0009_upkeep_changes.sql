@@ -0,0 +1,90 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+-- Every change to an item is logged once for each account that can see it, the id of the
+-- latest entry is the CalDAV sync token of that account.
+-- Bumped on every update, so an item completed to the same due date still gets a new ETag.
+ALTER TABLE upkeep_items
+  ADD revision INT NOT NULL DEFAULT 0;
+
+CREATE FUNCTION bump_upkeep_item_revision() RETURNS TRIGGER AS $$
+BEGIN
+  NEW.revision := OLD.revision + 1;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER upkeep_items_revision
+BEFORE UPDATE ON upkeep_items
+FOR EACH ROW EXECUTE FUNCTION bump_upkeep_item_revision();
+
+CREATE TABLE upkeep_changes (
+  id BIGSERIAL PRIMARY KEY,
+  account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  upkeep_item_id INT NOT NULL
+);
+
+CREATE INDEX upkeep_changes_account_index ON upkeep_changes (account_id, id);
+
+CREATE FUNCTION log_upkeep_item_change(item upkeep_items) RETURNS VOID AS $$
+  INSERT INTO upkeep_changes (account_id, upkeep_item_id)
+  SELECT item.account_id, item.id
+  WHERE item.household_id IS NULL
+  UNION
+  SELECT account_id, item.id
+  FROM household_members
+  WHERE household_id = item.household_id;
+$$ LANGUAGE SQL;
+
+CREATE FUNCTION log_upkeep_items_change() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP <> 'INSERT' THEN
+    PERFORM log_upkeep_item_change(OLD);
+  END IF;
+  IF TG_OP <> 'DELETE' THEN
+    PERFORM log_upkeep_item_change(NEW);
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER upkeep_items_change
+AFTER INSERT OR UPDATE OR DELETE ON upkeep_items
+FOR EACH ROW EXECUTE FUNCTION log_upkeep_items_change();
+
+-- Joining or leaving a household changes which items a member can see.
+CREATE FUNCTION log_household_members_change() RETURNS TRIGGER AS $$
+DECLARE
+  member household_members;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    member := OLD;
+  ELSE
+    member := NEW;
+  END IF;
+  INSERT INTO upkeep_changes (account_id, upkeep_item_id)
+  SELECT member.account_id, id
+  FROM upkeep_items
+  WHERE household_id = member.household_id;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER household_members_change
+AFTER INSERT OR DELETE ON household_members
+FOR EACH ROW EXECUTE FUNCTION log_household_members_change();
@@ -0,0 +1,118 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::Path,
+    http::{
+        header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, VARY},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+
+use crate::etag::is_unchanged;
+
+/// A file embedded by the build script, see `build.rs`.
+pub struct Asset {
+    pub path: &'static str,
+    pub hashed_path: &'static str,
+    pub content_type: &'static str,
+    pub hash: &'static str,
+    pub body: &'static [u8],
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// Both the plain and the hashed path lead to an asset, the flag tells if the path was hashed.
+static LOOKUP: Lazy<HashMap<&'static str, (&'static Asset, bool)>> = Lazy::new(|| {
+    ASSETS
+        .iter()
+        .flat_map(|asset| {
+            [
+                (asset.path, (asset, false)),
+                (asset.hashed_path, (asset, true)),
+            ]
+        })
+        .collect()
+});
+
+/// The URL of an asset by its path in `assets/` or `static/`. Unknown assets keep their path.
+/// The build script fails when a path written in a template is missing, so that only happens to
+/// paths that are put together at runtime.
+pub fn asset_url(path: &str) -> String {
+    match LOOKUP.get(path) {
+        Some((asset, _)) => format!("/static/{}", asset.hashed_path),
+        None => format!("/static/{}", path),
+    }
+}
+
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|part| {
+            let mut parameters = part.split(';').map(str::trim);
+            parameters.next() == Some(encoding)
+                && !parameters
+                    .any(|parameter| matches!(parameter, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"))
+        })
+}
+
+/// Hashed paths never change, so they're cached for a year. Plain paths are only revalidated.
+pub async fn get_asset(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    let Some(&(asset, is_hashed)) = LOOKUP.get(path.as_str()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let (encoding, body) = match (asset.brotli, asset.gzip) {
+        (Some(body), _) if accepts(&headers, "br") => (Some("br"), body),
+        (_, Some(body)) if accepts(&headers, "gzip") => (Some("gzip"), body),
+        _ => (None, asset.body),
+    };
+    let etag = match encoding {
+        Some(encoding) => format!("\"{}-{}\"", asset.hash, encoding),
+        None => format!("\"{}\"", asset.hash),
+    };
+    let cache_control = match is_hashed {
+        true => "public, max-age=31536000, immutable",
+        false => "no-cache",
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(CACHE_CONTROL, cache_control.parse().unwrap());
+    response_headers.insert(VARY, ACCEPT_ENCODING.into());
+    if let Ok(value) = etag.parse() {
+        response_headers.insert(ETAG, value);
+    }
+    if is_unchanged(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+    }
+
+    response_headers.insert(CONTENT_TYPE, asset.content_type.parse().unwrap());
+    if let Some(encoding) = encoding {
+        response_headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+    }
+    (response_headers, body).into_response()
+}
@@ -0,0 +1,145 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::http::HeaderValue;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use cookie::time::Duration as CookieDuration;
+
+#[derive(Clone, Debug)]
+pub struct CookieConfig {
+    /// The name of the session cookie. The cookies for guest CSRF tokens and flash messages keep
+    /// their own names, but share all other attributes.
+    pub session_name: Box<str>,
+    pub same_site: SameSite,
+    /// Sent as the `Domain` attribute, which shares the cookies with every subdomain. Without it,
+    /// only the exact host the server runs on gets them.
+    pub domain: Option<Box<str>>,
+    /// Prefixes every name with `__Host-`, browsers then refuse the cookies unless they are
+    /// secure, for the whole site and without a domain. Skipped in development mode.
+    pub host_prefix: bool,
+    /// How long a login lasts, both in the database and in the browser.
+    pub session_lifetime: Duration,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            session_name: "session_token".into(),
+            same_site: SameSite::Lax,
+            domain: None,
+            host_prefix: false,
+            session_lifetime: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl CookieConfig {
+    /// Catches combinations browsers would silently reject, which would look like logins that
+    /// don't stick.
+    pub fn validate(&self, dev_mode: bool) -> Result<()> {
+        if self.session_name.is_empty() {
+            return Err(anyhow!("The session cookie needs a name"));
+        }
+        if self.host_prefix && self.domain.is_some() {
+            return Err(anyhow!(
+                "Cookies with the __Host- prefix can't have a domain"
+            ));
+        }
+        if self.same_site == SameSite::None && dev_mode {
+            return Err(anyhow!(
+                "SameSite=None only works for secure cookies, which development mode turns off"
+            ));
+        }
+        if CookieDuration::try_from(self.session_lifetime).is_err() {
+            return Err(anyhow!("The session lifetime is too long"));
+        }
+        Ok(())
+    }
+}
+
+/// Builds every cookie the server sets, from the configuration and whether it runs in development
+/// mode. Cheap to clone, handlers get it as an extension.
+#[derive(Clone, Debug)]
+pub struct Cookies {
+    config: Arc<CookieConfig>,
+    secure: bool,
+}
+
+impl Cookies {
+    pub fn new(config: CookieConfig, dev_mode: bool) -> Self {
+        Self {
+            config: Arc::new(config),
+            secure: !dev_mode,
+        }
+    }
+
+    /// The name as the browser knows it, use this to read cookies as well.
+    pub fn name(&self, name: &str) -> String {
+        match self.config.host_prefix && self.secure {
+            true => format!("__Host-{}", name),
+            false => name.to_string(),
+        }
+    }
+
+    pub fn session_name(&self) -> String {
+        self.name(&self.config.session_name)
+    }
+
+    pub fn session_lifetime(&self) -> Duration {
+        self.config.session_lifetime
+    }
+
+    /// Lasts until the browser is closed, unless a lifetime is set on it.
+    pub fn build(&self, name: &str, value: impl Into<String>) -> Cookie<'static> {
+        let mut cookie = Cookie::build((self.name(name), value.into()))
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.config.same_site)
+            .build();
+        if let Some(domain) = &self.config.domain {
+            cookie.set_domain(domain.to_string());
+        }
+        cookie
+    }
+
+    pub fn session(&self, token: impl Into<String>) -> Cookie<'static> {
+        let mut cookie = self.build(&self.config.session_name, token);
+        cookie.set_max_age(CookieDuration::try_from(self.config.session_lifetime).ok());
+        cookie
+    }
+
+    /// Tells the browser to forget the cookie right away.
+    pub fn removal(&self, name: &str) -> Cookie<'static> {
+        let mut cookie = self.build(name, "");
+        cookie.make_removal();
+        cookie
+    }
+
+    pub fn session_removal(&self) -> Cookie<'static> {
+        self.removal(&self.config.session_name)
+    }
+}
+
+/// For the `Set-Cookie` header.
+pub fn header_value(cookie: &Cookie<'_>) -> Result<HeaderValue> {
+    Ok(HeaderValue::from_str(&cookie.to_string())?)
+}
@@ -18,24 +18,141 @@
 
 mod templates;
 
+use std::num::{ParseFloatError, ParseIntError};
+
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use tracing::warn;
+use tracing::{info, warn};
 
 use crate::extensions::Session;
 
-pub struct AppError(anyhow::Error);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Validation,
+    NotFound,
+    Conflict,
+    Unauthorized,
+    Forbidden,
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::Validation => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Conflict => StatusCode::CONFLICT,
+            ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorKind::Forbidden => StatusCode::FORBIDDEN,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorKind::Validation => "Invalid input",
+            ErrorKind::NotFound => "Not found",
+            ErrorKind::Conflict => "Conflict",
+            ErrorKind::Unauthorized => "Unauthorized",
+            ErrorKind::Forbidden => "Forbidden",
+            ErrorKind::Internal => "Server error",
+        }
+    }
+}
+
+/// The message is shown to the user, the source is only logged. Internal errors never show their
+/// source, it may contain details about the database.
+pub struct AppError {
+    kind: ErrorKind,
+    message: Box<str>,
+    source: anyhow::Error,
+}
+
+/// Left on an error response, so [crate::middleware::render_errors] can render it the way the
+/// request expects.
+#[derive(Clone, Debug)]
+pub struct ErrorDetails {
+    pub kind: ErrorKind,
+    pub message: Box<str>,
+}
+
+impl AppError {
+    fn with_message(kind: ErrorKind, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            kind,
+            source: anyhow::Error::msg(message.clone()),
+            message: message.into(),
+        }
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::with_message(ErrorKind::Validation, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::with_message(ErrorKind::NotFound, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::with_message(ErrorKind::Conflict, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::with_message(ErrorKind::Unauthorized, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::with_message(ErrorKind::Forbidden, message)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+/// Recognizes the errors that are caused by the request rather than the server, anything else
+/// is internal.
+fn classify(error: &anyhow::Error) -> (ErrorKind, &'static str) {
+    if let Some(error) = error.downcast_ref::<sqlx::Error>() {
+        return match error {
+            sqlx::Error::RowNotFound => (ErrorKind::NotFound, "This could not be found"),
+            sqlx::Error::Database(error) if error.is_unique_violation() => {
+                (ErrorKind::Conflict, "This already exists")
+            }
+            sqlx::Error::Database(error) if error.is_check_violation() => {
+                (ErrorKind::Validation, "One of the values is not allowed")
+            }
+            _ => (ErrorKind::Internal, "Something went wrong"),
+        };
+    }
+    if error.is::<ParseIntError>() {
+        return (ErrorKind::Validation, "Expected a whole number");
+    }
+    if error.is::<ParseFloatError>() {
+        return (ErrorKind::Validation, "Expected a number");
+    }
+    if error.is::<chrono::ParseError>() {
+        return (ErrorKind::Validation, "Expected a date");
+    }
+    (ErrorKind::Internal, "Something went wrong")
+}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        warn!("Server error: {}", self.0);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Something went wrong!".to_string(),
-        )
-            .into_response()
+        match self.kind {
+            ErrorKind::Internal => warn!("Server error: {:?}", self.source),
+            kind => info!("Request error ({:?}): {}", kind, self.source),
+        }
+
+        let mut response = (self.kind.status(), self.message.to_string()).into_response();
+        response.extensions_mut().insert(ErrorDetails {
+            kind: self.kind,
+            message: self.message,
+        });
+        response
     }
 }
 
@@ -44,19 +161,46 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        let source = err.into();
+        let (kind, message) = classify(&source);
+        Self {
+            kind,
+            message: message.into(),
+            source,
+        }
     }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
 
-pub fn unauthorized_error(session: Session) -> impl IntoResponse {
+/// A full page for regular requests, and only the message for htmx requests. The message replaces
+/// the `#error-message` placeholder in the layout, wherever the request meant to swap.
+pub fn render_error(details: ErrorDetails, session: Session, is_htmx: bool) -> Response {
+    let status = details.kind.status();
+    if is_htmx {
+        return (
+            status,
+            [
+                ("HX-Retarget", "#error-message"),
+                ("HX-Reswap", "outerHTML"),
+                ("HX-Reselect", "#error-message"),
+            ],
+            templates::ErrorFragment {
+                title: details.kind.title(),
+                message: details.message,
+            },
+        )
+            .into_response();
+    }
+
     (
-        StatusCode::UNAUTHORIZED,
-        templates::UnauthorizedError { session },
+        status,
+        templates::ErrorPage {
+            code: status.as_u16(),
+            title: details.kind.title(),
+            message: details.message,
+            session,
+        },
     )
-}
-
-pub fn server_error() -> impl IntoResponse {
-    (StatusCode::INTERNAL_SERVER_ERROR, templates::ServerError)
+        .into_response()
 }
@@ -21,11 +21,17 @@ use askama::Template;
 use crate::extensions::Session;
 
 #[derive(Template)]
-#[template(path = "error/unauthorized.html")]
-pub struct UnauthorizedError {
+#[template(path = "error/error.html")]
+pub struct ErrorPage {
+    pub code: u16,
+    pub title: &'static str,
+    pub message: Box<str>,
     pub session: Session,
 }
 
 #[derive(Template)]
-#[template(path = "error/server_error.html")]
-pub struct ServerError;
+#[template(path = "error/error.part.html")]
+pub struct ErrorFragment {
+    pub title: &'static str,
+    pub message: Box<str>,
+}
@@ -0,0 +1,63 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Conditional requests, shared by everything that tags its responses.
+
+use axum::http::{header::IF_NONE_MATCH, HeaderMap};
+
+/// Checks an `If-Match` or `If-None-Match` header value against the current ETag. The comparison
+/// is weak, a `W/` on either side is ignored.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Whether the client already has the response tagged `etag`, and can get 304 Not Modified.
+pub fn is_unchanged(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_match_weakly() {
+        assert!(etag_matches("\"a\"", "\"a\""));
+        assert!(etag_matches("W/\"a\"", "\"a\""));
+        assert!(etag_matches("\"a\"", "W/\"a\""));
+        assert!(etag_matches("\"other\", W/\"a\"", "\"a\""));
+        assert!(etag_matches("*", "\"a\""));
+        assert!(!etag_matches("\"other\"", "\"a\""));
+        assert!(!etag_matches("a", "\"a\""));
+    }
+
+    #[test]
+    fn only_if_none_match_counts_as_unchanged() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_unchanged(&headers, "\"a\""));
+        headers.insert(IF_NONE_MATCH, "W/\"a\"".parse().unwrap());
+        assert!(is_unchanged(&headers, "\"a\""));
+        assert!(!is_unchanged(&headers, "\"b\""));
+    }
+}
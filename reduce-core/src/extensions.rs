@@ -50,3 +50,7 @@ impl From<AuthorizedSession> for Session {
         }
     }
 }
+
+/// The token forms have to submit, for guests as well as sessions. Guests only need it to log in.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(pub Arc<str>);
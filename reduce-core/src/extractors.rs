@@ -16,4 +16,5 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-pub mod csrf_form;
+pub mod hx_request;
+pub mod valid_form;
@@ -0,0 +1,44 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use async_trait::async_trait;
+use axum::{
+    extract::{rejection::FormRejection, FromRequest, Request},
+    Form,
+};
+
+use crate::validation::{FormState, Validate};
+
+/// A [Form] that is checked against the rules of its fields. An invalid form isn't rejected,
+/// the handler gets the submitted state back so it can render the form again.
+pub struct ValidForm<T>(pub Result<T, FormState>);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidForm<T>
+where
+    S: Send + Sync + 'static,
+    Form<T>: FromRequest<S, Rejection = FormRejection>,
+    T: Validate,
+{
+    type Rejection = FormRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Form(form) = Form::<T>::from_request(req, state).await?;
+        Ok(ValidForm(form.validate().map(|_| form)))
+    }
+}
@@ -0,0 +1,93 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+mod templates;
+
+use std::sync::{Arc, Mutex};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+pub use templates::FlashFragment;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashLevel {
+    Success,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FlashMessage {
+    pub level: FlashLevel,
+    pub text: Box<str>,
+}
+
+/// Messages for the user that are shown once, on whatever is rendered next. Handlers take it as
+/// an extension and add to it, the layout takes the messages out again.
+#[derive(Clone, Default)]
+pub struct Flash(Arc<Mutex<Vec<FlashMessage>>>);
+
+impl Flash {
+    pub fn new(messages: Vec<FlashMessage>) -> Self {
+        Self(Arc::new(Mutex::new(messages)))
+    }
+
+    pub fn success(&self, text: impl Into<Box<str>>) {
+        self.push(FlashLevel::Success, text.into());
+    }
+
+    pub fn warning(&self, text: impl Into<Box<str>>) {
+        self.push(FlashLevel::Warning, text.into());
+    }
+
+    pub fn error(&self, text: impl Into<Box<str>>) {
+        self.push(FlashLevel::Error, text.into());
+    }
+
+    fn push(&self, level: FlashLevel, text: Box<str>) {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(FlashMessage { level, text });
+    }
+
+    pub fn take(&self) -> Vec<FlashMessage> {
+        std::mem::take(
+            &mut *self
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+}
+
+/// Messages that weren't shown yet travel to the next request in a cookie.
+pub fn encode_messages(messages: &[FlashMessage]) -> Option<String> {
+    Some(URL_SAFE_NO_PAD.encode(serde_json::to_vec(messages).ok()?))
+}
+
+/// A cookie that can't be read is dropped, the messages it had aren't worth an error.
+pub fn decode_messages(value: &str) -> Vec<FlashMessage> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
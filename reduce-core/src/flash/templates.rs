@@ -18,14 +18,11 @@
 
 use askama::Template;
 
-use crate::extensions::Session;
+use super::FlashMessage;
 
+/// Adds messages to the page of an htmx request, whatever part of the page it swaps.
 #[derive(Template)]
-#[template(path = "error/unauthorized.html")]
-pub struct UnauthorizedTemplate {
-    pub session: Session,
+#[template(path = "flash/messages.part.html")]
+pub struct FlashFragment {
+    pub messages: Box<[FlashMessage]>,
 }
-
-#[derive(Template)]
-#[template(path = "error/server_error.html")]
-pub struct ServerErrorTemplate;
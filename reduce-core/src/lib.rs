@@ -16,23 +16,43 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+mod assets;
+mod cookies;
 mod error;
+mod etag;
 mod extensions;
 mod extractors;
+mod flash;
 mod middleware;
 mod routes;
 mod sections;
 mod template_extend;
+mod tls;
+mod validation;
 
-use std::{env, error::Error, sync::Arc};
+use std::{
+    error::Error,
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use askama::Template;
 use axum::{Extension, Router};
+use cookies::Cookies;
 use extensions::Session;
-use middleware::inject_user_authorization::InjectUserAuthorization;
+use middleware::{
+    compression::compression, conditional_get::conditional_get, flash_messages::flash_messages,
+    inject_user_authorization::InjectUserAuthorization, render_errors::render_errors,
+    require_csrf::require_csrf, security_headers::security_headers,
+};
 use sections::{ModuleRegistration, SectionRegistration};
-use template_extend::{set_navigation_links, NavigationLink};
-use tracing::{Level, Subscriber};
+use sqlx::postgres::PgPoolOptions;
+use template_extend::{set_dev_mode, set_navigation_links, NavigationLink};
+use tls::{serve_redirect, watch_certificates};
+use tokio::sync::watch;
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::FmtSubscriber;
 
 #[derive(Template)]
@@ -41,57 +61,132 @@ struct IndexTemplate {
     session: Session,
 }
 
-pub fn setup_tracing() -> Result<(), Box<dyn Error>> {
-    let is_production = matches!(env::var("ENVIRONMENT").as_deref(), Ok("production"));
-    let mut _guard = None;
-    let tracing_subscriber: Box<dyn Subscriber + Send + Sync + 'static> =
-        match env::var("LOG_DIRECTORY") {
-            Ok(log_directory) if is_production => {
-                let file_appender = tracing_appender::rolling::daily(log_directory, "reduce.json");
-                let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
-                _guard = Some(guard);
-                Box::from(
-                    tracing_subscriber::fmt()
-                        .json()
-                        .with_writer(non_blocking)
-                        .with_max_level(Level::TRACE)
-                        .finish(),
-                )
-            }
-            _ => Box::from(
-                FmtSubscriber::builder()
-                    .with_max_level(Level::TRACE)
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// Writes JSON to a new file in this directory every day, instead of text to the terminal.
+    pub directory: Option<PathBuf>,
+    pub max_level: Level,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            max_level: Level::TRACE,
+        }
+    }
+}
+
+/// Keeps the thread that writes log files running, it stops once this is dropped.
+static LOG_WRITER: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+
+/// Can only be done once per process, a server started again keeps the logging it had.
+pub fn setup_tracing(config: &LogConfig) -> Result<(), Box<dyn Error>> {
+    let mut guard = None;
+    let tracing_subscriber: Box<dyn Subscriber + Send + Sync + 'static> = match &config.directory {
+        Some(log_directory) => {
+            let file_appender = tracing_appender::rolling::daily(log_directory, "reduce.json");
+            let (non_blocking, worker_guard) = tracing_appender::non_blocking(file_appender);
+            guard = Some(worker_guard);
+            Box::from(
+                tracing_subscriber::fmt()
+                    .json()
+                    .with_writer(non_blocking)
+                    .with_max_level(config.max_level)
                     .finish(),
-            ),
-        };
+            )
+        }
+        None => Box::from(
+            FmtSubscriber::builder()
+                .with_max_level(config.max_level)
+                .finish(),
+        ),
+    };
 
     tracing::subscriber::set_global_default(tracing_subscriber)?;
+    if let Ok(mut writer) = LOG_WRITER.lock() {
+        *writer = guard;
+    }
     Ok(())
 }
 
-#[derive(Debug)]
+pub use axum_extra::extract::cookie::SameSite;
+pub use cookies::CookieConfig;
+pub use middleware::security_headers::SecurityHeadersConfig;
+pub use sections::Module;
+pub use tls::TlsConfig;
+pub use tracing::Level;
+
+#[derive(Clone, Debug)]
 pub struct ServerConfig {
     pub db_url: Box<str>,
     pub server_bind_address: Box<str>,
+    pub security_headers: SecurityHeadersConfig,
+    pub cookies: CookieConfig,
+    /// For running locally over plain HTTP. Cookies are no longer secure and every page warns
+    /// about it, never use this for a server others can reach.
+    pub dev_mode: bool,
+    /// Serves HTTPS directly, instead of relying on a proxy in front of the server.
+    pub tls: Option<TlsConfig>,
+    /// Only read by `setup_tracing`, which has to run before the server starts.
+    pub log: LogConfig,
+    pub max_database_connections: u32,
+    pub modules: Box<[Module]>,
+    /// Creates the first account, see the bootstrapping guide. Leave it out once that's done.
+    pub bootstrap_secret: Option<Box<str>>,
+}
+
+impl ServerConfig {
+    /// Everything else starts out with the settings a public server needs.
+    pub fn new(db_url: impl Into<Box<str>>, server_bind_address: impl Into<Box<str>>) -> Self {
+        Self {
+            db_url: db_url.into(),
+            server_bind_address: server_bind_address.into(),
+            security_headers: Default::default(),
+            cookies: Default::default(),
+            dev_mode: false,
+            tls: None,
+            log: Default::default(),
+            max_database_connections: 10,
+            modules: Box::from(Module::ALL),
+            bootstrap_secret: None,
+        }
+    }
 }
 
-pub async fn start_server(config: ServerConfig) -> Result<(), Box<dyn Error>> {
-    dotenv::dotenv()?;
+/// Runs until `shutdown` completes. Requests that are already being handled still get their
+/// response, then the database connections are closed.
+pub async fn start_server(
+    mut config: ServerConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), Box<dyn Error>> {
+    config.cookies.validate(config.dev_mode)?;
+    if config.dev_mode {
+        tracing::warn!("Running in development mode, cookies are sent over plain HTTP");
+        // Browsers would insist on HTTPS for this host from then on.
+        config.security_headers.hsts_max_age_seconds = None;
+    }
+    set_dev_mode(config.dev_mode);
+    let cookies = Cookies::new(config.cookies, config.dev_mode);
 
     let db_url = config.db_url;
-    let db_pool = sqlx::postgres::PgPool::connect(&db_url).await?;
+    let db_pool = PgPoolOptions::new()
+        .max_connections(config.max_database_connections)
+        .connect(&db_url)
+        .await?;
 
     sqlx::migrate!("./migrations").run(&db_pool).await?;
 
-    let registrations = [sections::register()];
+    let registrations = [sections::register(
+        &config.modules,
+        config.bootstrap_secret.map(Arc::from),
+    )];
 
     let mut app = Router::new();
-    let mut all_navigation_links = Vec::from([
-        NavigationLink {
-            href: "/".into(),
-            title: "Home".into(),
-        },
-    ]);
+    let mut all_navigation_links = Vec::from([NavigationLink {
+        href: "/".into(),
+        title: "Home".into(),
+    }]);
 
     for ModuleRegistration {
         default_module_name,
@@ -101,15 +196,22 @@ pub async fn start_server(config: ServerConfig) -> Result<(), Box<dyn Error>> {
         let mut module_router = Router::new();
         for SectionRegistration {
             router,
+            root_router,
             entry_page,
             title: name,
+            tasks,
         } in sections.as_ref()
         {
+            for spawn in tasks.iter() {
+                spawn(db_pool.clone());
+            }
             module_router = module_router.merge(router.to_owned());
-            if !entry_page.is_empty() { all_navigation_links.push(NavigationLink {
+            app = app.merge(root_router.to_owned());
+            if !entry_page.is_empty() {
+                all_navigation_links.push(NavigationLink {
                     href: format!("{}{}", default_module_name, entry_page).into(),
                     title: Box::from(*name),
-                })
+                });
             }
         }
 
@@ -117,15 +219,84 @@ pub async fn start_server(config: ServerConfig) -> Result<(), Box<dyn Error>> {
     }
 
     let app = routes::register(app)
+        .layer(axum::middleware::from_fn_with_state(
+            cookies.clone(),
+            require_csrf,
+        ))
+        .layer(axum::middleware::from_fn(render_errors))
+        .layer(axum::middleware::from_fn_with_state(
+            cookies.clone(),
+            flash_messages,
+        ))
+        .layer(axum::middleware::from_fn(conditional_get))
         .layer(Extension(db_pool.clone()))
-        .layer(InjectUserAuthorization { pool: db_pool });
+        .layer(Extension(cookies.clone()))
+        .layer(InjectUserAuthorization {
+            pool: db_pool.clone(),
+            cookies,
+        })
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(config.security_headers),
+            security_headers,
+        ))
+        .layer(compression());
 
     set_navigation_links(Arc::from(all_navigation_links))?;
 
-    // run our app with hyper, listening globally on port 3000
-    let listener = tokio::net::TcpListener::bind(&*config.server_bind_address)
-        .await
-        .unwrap();
-    axum::serve(listener, app).await?;
-    Ok(())
+    // Both listeners have to stop, so the shutdown is passed on to each of them.
+    let (stop, stopping) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown.await;
+        tracing::info!("Shutting down, waiting for open requests to finish");
+        let _ = stop.send(true);
+    });
+    let stopped = move || {
+        let mut stopping = stopping.clone();
+        async move {
+            let _ = stopping.wait_for(|stopping| *stopping).await;
+        }
+    };
+
+    // Listens on the configured address, over TLS when it's set up.
+    let listener = tokio::net::TcpListener::bind(&*config.server_bind_address).await?;
+    let served = match config.tls {
+        Some(tls) => {
+            let rustls = tls.load().await?;
+            watch_certificates(tls.clone(), rustls.clone());
+            if let Some(redirect_bind_address) = &tls.redirect_bind_address {
+                let redirect_listener =
+                    tokio::net::TcpListener::bind(&**redirect_bind_address).await?;
+                let https_port = listener.local_addr()?.port();
+                let stopped = stopped();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_redirect(redirect_listener, https_port, stopped).await
+                    {
+                        tracing::error!("The redirect to HTTPS stopped: {}", error);
+                    }
+                });
+            }
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                let stopped = stopped();
+                async move {
+                    stopped.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+        }
+        None => {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(stopped())
+                .await
+        }
+    };
+
+    db_pool.close().await;
+    tracing::info!("The server stopped");
+    Ok(served?)
 }
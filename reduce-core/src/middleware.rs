@@ -16,5 +16,11 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+pub mod compression;
+pub mod conditional_get;
+pub mod flash_messages;
 pub mod inject_user_authorization;
+pub mod render_errors;
 pub mod require_authentication;
+pub mod require_csrf;
+pub mod security_headers;
@@ -0,0 +1,120 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{etag::etag_matches, template_extend::csp_nonce};
+
+/// Pages are hashed in memory, anything bigger than this is sent without a tag.
+const MAX_PAGE_SIZE: u64 = 1024 * 1024;
+
+/// Only rendered pages get a tag. Exports and CalDAV responses can be large and are streamed as
+/// they are, static assets bring their own.
+fn is_page(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+/// Hashes the body as if the nonce wasn't there, it changes with every request while the page
+/// stays the same.
+fn content_hash(body: &[u8], nonce: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    let mut rest = body;
+    if !nonce.is_empty() {
+        while let Some(position) = rest.windows(nonce.len()).position(|part| part == nonce) {
+            hasher.update(&rest[..position]);
+            rest = &rest[position + nonce.len()..];
+        }
+    }
+    hasher.update(rest);
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Gives rendered pages a weak ETag and answers with 304 Not Modified when the browser already
+/// has the same page. Pages are still rendered, but nothing has to be sent over the network.
+/// This has to run inside the layer that sets the nonce.
+pub async fn conditional_get(req: Request, next: Next) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+    let if_none_match = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(Box::<str>::from);
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK
+        || response.headers().contains_key(ETAG)
+        || !is_page(&response)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let fits = body
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= MAX_PAGE_SIZE);
+    if !fits {
+        return Response::from_parts(parts, body);
+    }
+    let body = match to_bytes(body, MAX_PAGE_SIZE as usize).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let nonce = csp_nonce();
+    let hash = content_hash(&body, nonce.as_bytes());
+
+    // Pages depend on the session, so only the browser may keep them, and it has to ask first.
+    parts
+        .headers
+        .entry(CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("private, no-cache"));
+
+    let tag = format!("\"{}\"", hash);
+    if let Ok(value) = HeaderValue::from_str(&format!("W/{}", tag)) {
+        parts.headers.insert(ETAG, value);
+    }
+    if if_none_match
+        .as_deref()
+        .is_some_and(|value| etag_matches(value, &tag))
+    {
+        parts.headers.remove(CONTENT_TYPE);
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.status = StatusCode::NOT_MODIFIED;
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(body))
+}
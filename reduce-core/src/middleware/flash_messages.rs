@@ -0,0 +1,129 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use askama::Template;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION, SET_COOKIE},
+        StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+
+use crate::{
+    cookies::{header_value, Cookies},
+    flash::{decode_messages, encode_messages, Flash, FlashFragment, FlashMessage},
+    template_extend::with_flash,
+};
+
+const FLASH_COOKIE: &str = "flash";
+
+/// Whether htmx puts the response on the page, in which case messages can go along with it.
+fn is_swapped(response: &Response) -> bool {
+    let headers = response.headers();
+    let is_html = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let is_redirect = [
+        LOCATION.as_str(),
+        "HX-Location",
+        "HX-Redirect",
+        "HX-Refresh",
+    ]
+    .iter()
+    .any(|name| headers.contains_key(*name));
+    let is_shown = response.status().is_success()
+        || response.status() == StatusCode::UNPROCESSABLE_ENTITY
+        || headers.contains_key("HX-Retarget");
+    is_html && !is_redirect && is_shown
+}
+
+fn set_cookie(response: &mut Response, cookie: Cookie<'static>) {
+    if let Ok(cookie) = header_value(&cookie) {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+}
+
+async fn append_fragment(response: Response, messages: Vec<FlashMessage>) -> Response {
+    let fragment = match (FlashFragment {
+        messages: messages.into(),
+    })
+    .render()
+    {
+        Ok(fragment) => fragment,
+        Err(_) => return response,
+    };
+    let (mut parts, body) = response.into_parts();
+    let mut body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => Vec::from(body),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    body.extend_from_slice(fragment.as_bytes());
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Makes a [Flash] available to handlers, and makes sure what they add is shown exactly once. Full
+/// pages show the messages in their layout, htmx responses get them as an out of band swap, and
+/// anything else, like a redirect, leaves them for the next request.
+pub async fn flash_messages(
+    State(cookies): State<Cookies>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let is_htmx = req.headers().contains_key("HX-Request");
+    let pending = CookieJar::from_headers(req.headers())
+        .get(&cookies.name(FLASH_COOKIE))
+        .map(|cookie| decode_messages(cookie.value()));
+    let had_cookie = pending.is_some();
+    let pending = pending.unwrap_or_default();
+
+    let flash = Flash::new(pending.clone());
+    req.extensions_mut().insert(flash.clone());
+    let mut response = match is_htmx {
+        true => next.run(req).await,
+        false => with_flash(flash.clone(), next.run(req)).await,
+    };
+
+    let remaining = flash.take();
+    if remaining.is_empty() {
+        if had_cookie {
+            set_cookie(&mut response, cookies.removal(FLASH_COOKIE));
+        }
+        return response;
+    }
+    if is_htmx && is_swapped(&response) {
+        let mut response = append_fragment(response, remaining).await;
+        if had_cookie {
+            set_cookie(&mut response, cookies.removal(FLASH_COOKIE));
+        }
+        return response;
+    }
+    // Requests for assets and the like leave the cookie as it is.
+    if remaining != pending {
+        if let Some(value) = encode_messages(&remaining) {
+            set_cookie(&mut response, cookies.build(FLASH_COOKIE, value));
+        }
+    }
+    response
+}
@@ -24,12 +24,13 @@ use chrono::{Local, NaiveDateTime};
 use sqlx::{query_as, Pool, Postgres};
 use tower::{Layer, Service};
 
-use crate::extensions::Session;
+use crate::{cookies::Cookies, extensions::Session};
 
 #[derive(Clone, Debug)]
 pub struct InjectUserAuthorizationService<Inner> {
     inner: Inner,
     pool: Pool<Postgres>,
+    cookies: Cookies,
 }
 
 #[derive(Debug)]
@@ -62,7 +63,7 @@ where
     fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         let cookies = CookieJar::from_headers(req.headers());
         let session_token: Option<Box<str>> = cookies
-            .get("session_token")
+            .get(&self.cookies.session_name())
             .map(|token| token.value().into());
 
         let pool = self.pool.clone();
@@ -109,6 +110,7 @@ where
 #[derive(Clone, Debug)]
 pub struct InjectUserAuthorization {
     pub pool: Pool<Postgres>,
+    pub cookies: Cookies,
 }
 
 impl<Inner> Layer<Inner> for InjectUserAuthorization {
@@ -118,6 +120,7 @@ impl<Inner> Layer<Inner> for InjectUserAuthorization {
         InjectUserAuthorizationService {
             inner,
             pool: self.pool.clone(),
+            cookies: self.cookies.clone(),
         }
     }
 }
@@ -0,0 +1,41 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::{
+    error::{render_error, ErrorDetails},
+    extensions::Session,
+};
+
+/// Turns the bare responses of [crate::error::AppError] into a page or an htmx fragment. This
+/// has to run inside the layer that injects the session.
+pub async fn render_errors(req: Request, next: Next) -> Response {
+    let is_htmx = req.headers().contains_key("HX-Request");
+    let session = req
+        .extensions()
+        .get::<Session>()
+        .cloned()
+        .unwrap_or(Session::Guest);
+
+    let response = next.run(req).await;
+    match response.extensions().get::<ErrorDetails>() {
+        Some(details) => render_error(details.clone(), session, is_htmx),
+        None => response,
+    }
+}
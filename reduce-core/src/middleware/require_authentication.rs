@@ -16,14 +16,16 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-mod templates;
-
-use askama_axum::IntoResponse;
-use axum::{extract::Request, middleware::Next, response::Response};
+use anyhow::anyhow;
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 
 use crate::{
+    error::AppError,
     extensions::{AuthorizedSession, Session},
-    middleware::require_authentication::templates::{ServerErrorTemplate, UnauthorizedTemplate},
 };
 
 pub async fn require_authentication(mut req: Request, next: Next) -> Response {
@@ -33,7 +35,8 @@ pub async fn require_authentication(mut req: Request, next: Next) -> Response {
     let authentication_status = if let Some(authentication_status) = authentication_status {
         authentication_status
     } else {
-        return ServerErrorTemplate.into_response();
+        return AppError::from(anyhow!("The session of the request was never looked up"))
+            .into_response();
     };
 
     match authentication_status {
@@ -49,9 +52,6 @@ pub async fn require_authentication(mut req: Request, next: Next) -> Response {
             });
             next.run(req).await
         }
-        _ => UnauthorizedTemplate {
-            session: authentication_status.clone(),
-        }
-        .into_response(),
+        _ => AppError::unauthorized("Log in to see this page").into_response(),
     }
 }
@@ -0,0 +1,163 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_TYPE, SET_COOKIE},
+        Method,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    cookies::{header_value, Cookies},
+    error::AppError,
+    extensions::{CsrfToken, Session},
+};
+
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+const GUEST_COOKIE: &str = "csrf_token";
+/// The same limit axum uses for form bodies by default.
+const FORM_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// CalDAV clients authenticate with basic authentication on every request and never have a
+/// token. Browsers do remember basic credentials, so what makes these safe is the method: a form
+/// on another site can only send GET and POST, and anything else needs a CORS preflight that is
+/// never granted. A POST to these paths is still checked.
+const EXEMPT_PATHS: &[&str] = &["/core/caldav", "/.well-known/caldav"];
+const EXEMPT_METHODS: &[&str] = &[
+    "PUT",
+    "DELETE",
+    "PROPFIND",
+    "PROPPATCH",
+    "REPORT",
+    "MKCOL",
+    "MKCALENDAR",
+    "COPY",
+    "MOVE",
+    "LOCK",
+    "UNLOCK",
+];
+
+#[derive(Deserialize)]
+struct TokenField {
+    csrf_token: Option<Box<str>>,
+}
+
+fn is_exempt(method: &Method, path: &str) -> bool {
+    let is_dav_path = EXEMPT_PATHS.iter().any(|exempt| {
+        path.strip_prefix(exempt)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    is_dav_path && EXEMPT_METHODS.contains(&method.as_str())
+}
+
+fn generate_guest_token() -> Arc<str> {
+    let mut bytes = [0u8; 18];
+    OsRng.fill_bytes(&mut bytes);
+    STANDARD.encode(bytes).into()
+}
+
+/// The header wins, otherwise a url encoded body is read for a `csrf_token` field. Other bodies,
+/// like multipart uploads, have to use the header.
+async fn submitted_token(req: Request) -> (Request, Option<Box<str>>) {
+    if let Some(token) = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        let token = token.into();
+        return (req, Some(token));
+    }
+
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return (req, None);
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes = match to_bytes(body, FORM_BODY_LIMIT).await {
+        Ok(bytes) => bytes,
+        Err(_) => return (Request::from_parts(parts, Body::empty()), None),
+    };
+    let token = serde_urlencoded::from_bytes::<TokenField>(&bytes)
+        .ok()
+        .and_then(|field| field.csrf_token);
+    (Request::from_parts(parts, Body::from(bytes)), token)
+}
+
+/// Rejects every unsafe request without the token of the current session. Guests don't have a
+/// session, they get a token in a cookie that has to be submitted along with the form as well.
+pub async fn require_csrf(
+    State(cookies): State<Cookies>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let session = req.extensions().get::<Session>().cloned();
+    let guest_cookie: Option<Arc<str>> = CookieJar::from_headers(req.headers())
+        .get(&cookies.name(GUEST_COOKIE))
+        .map(|cookie| cookie.value().into());
+
+    let (expected, new_guest_cookie) = match session {
+        Some(Session::Authenticated { csrf_token, .. }) => (csrf_token, None),
+        _ => match guest_cookie {
+            Some(token) => (token, None),
+            None => {
+                let token = generate_guest_token();
+                (token.clone(), Some(token))
+            }
+        },
+    };
+    req.extensions_mut().insert(CsrfToken(expected.clone()));
+
+    if !req.method().is_safe() && !is_exempt(req.method(), req.uri().path()) {
+        let (checked_req, submitted) = submitted_token(req).await;
+        req = checked_req;
+        // A freshly generated guest token can't have been submitted, the form was never shown.
+        let is_valid = new_guest_cookie.is_none()
+            && submitted.is_some_and(|submitted| {
+                bool::from(submitted.as_bytes().ct_eq(expected.as_bytes()))
+            });
+        if !is_valid {
+            return AppError::forbidden("The form has expired, reload the page and try again")
+                .into_response();
+        }
+    }
+
+    let mut response = next.run(req).await;
+    if let Some(token) = new_guest_cookie {
+        if let Ok(cookie) = header_value(&cookies.build(GUEST_COOKIE, token.as_ref())) {
+            response.headers_mut().append(SET_COOKIE, cookie);
+        }
+    }
+    response
+}
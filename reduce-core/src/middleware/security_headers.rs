@@ -0,0 +1,132 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::template_extend::with_csp_nonce;
+
+#[derive(Clone, Debug)]
+pub struct SecurityHeadersConfig {
+    /// Sent as Strict-Transport-Security. Leave it out while the server is only reachable over
+    /// plain HTTP, browsers remember it for this long.
+    pub hsts_max_age_seconds: Option<u64>,
+    pub frame_options: Box<str>,
+    pub referrer_policy: Box<str>,
+    pub permissions_policy: Box<str>,
+    /// Allowed in `script-src` next to the server itself and the nonce of the page.
+    pub script_sources: Box<[Box<str>]>,
+    /// Reports violations to the browser console without blocking anything, useful to try a
+    /// stricter policy first.
+    pub report_only: bool,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            hsts_max_age_seconds: Some(365 * 24 * 60 * 60),
+            frame_options: "DENY".into(),
+            referrer_policy: "same-origin".into(),
+            permissions_policy: "camera=(), microphone=(), geolocation=(), payment=(), usb=()"
+                .into(),
+            script_sources: Box::from([]),
+            report_only: false,
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// Scripts only run with the nonce of the page, and nothing is ever evaluated from a string.
+    /// That's why Alpine is the CSP build, which only looks up the components registered in
+    /// `components.js`.
+    fn content_security_policy(&self, nonce: &str) -> String {
+        let script_sources = self
+            .script_sources
+            .iter()
+            .map(|source| format!(" {}", source))
+            .collect::<String>();
+        format!(
+            "default-src 'self'; \
+            script-src 'self' 'nonce-{}'{}; \
+            style-src 'self'; \
+            img-src 'self' data:; \
+            connect-src 'self'; \
+            object-src 'none'; \
+            base-uri 'self'; \
+            form-action 'self'; \
+            frame-ancestors 'none'",
+            nonce, script_sources
+        )
+    }
+}
+
+fn generate_nonce() -> Arc<str> {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    STANDARD.encode(bytes).into()
+}
+
+/// Headers a handler already set are left alone. Every request gets a new nonce.
+pub async fn security_headers(
+    State(config): State<Arc<SecurityHeadersConfig>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let nonce = generate_nonce();
+    let mut response = with_csp_nonce(nonce.clone(), next.run(req)).await;
+
+    let policy_header = match config.report_only {
+        true => "content-security-policy-report-only",
+        false => "content-security-policy",
+    };
+    let mut headers = vec![
+        ("x-content-type-options", "nosniff".to_string()),
+        ("x-frame-options", config.frame_options.to_string()),
+        ("referrer-policy", config.referrer_policy.to_string()),
+        ("permissions-policy", config.permissions_policy.to_string()),
+    ];
+    // A 304 makes the browser reuse the page it has, along with the policy it got with that page.
+    // A new policy would replace it, and the nonce in the page wouldn't match anymore.
+    if response.status() != StatusCode::NOT_MODIFIED {
+        headers.push((policy_header, config.content_security_policy(&nonce)));
+    }
+    if let Some(max_age) = config.hsts_max_age_seconds {
+        headers.push((
+            "strict-transport-security",
+            format!("max-age={}; includeSubDomains", max_age),
+        ));
+    }
+
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response
+                .headers_mut()
+                .entry(HeaderName::from_static(name))
+                .or_insert(value);
+        }
+    }
+    response
+}
@@ -16,27 +16,16 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use askama_axum::IntoResponse;
-use axum::{http::HeaderMap, routing::get, Extension, Router};
+use axum::{routing::get, Extension, Router};
 
-use crate::{error::AppResult, extensions::Session, IndexTemplate};
+use crate::{assets::get_asset, extensions::Session, IndexTemplate};
 
 async fn index(Extension(session): Extension<Session>) -> IndexTemplate {
     IndexTemplate { session }
 }
 
-async fn get_style() -> AppResult<impl IntoResponse> {
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-type", "text/css".parse()?);
-
-    Ok((
-        headers,
-        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/static/style.css")),
-    ))
-}
-
 pub fn register(router: Router) -> Router {
     router
         .route("/", get(index))
-        .route("/static/style.css", get(get_style))
+        .route("/static/*path", get(get_asset))
 }
@@ -20,12 +20,20 @@ mod account;
 mod auth;
 mod upkeep;
 
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
 use axum::Router;
+use sqlx::{Pool, Postgres};
 
 pub struct SectionRegistration {
     pub router: Router,
+    /// Routes outside of the module, like well-known URIs.
+    pub root_router: Router,
     pub entry_page: &'static str,
     pub title: &'static str,
+    /// Work that keeps running next to the requests, for as long as the server does.
+    pub tasks: &'static [fn(Pool<Postgres>)],
 }
 
 pub struct ModuleRegistration {
@@ -33,8 +41,50 @@ pub struct ModuleRegistration {
     pub sections: Box<[SectionRegistration]>,
 }
 
-pub fn register() -> ModuleRegistration {
-    let sections = Box::from([auth::register(), account::register(), upkeep::register()]);
+/// The parts of Reduce a server can leave out. Logging in and the account page are always there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Module {
+    Upkeep,
+    Focus,
+    CalDav,
+}
+
+impl Module {
+    pub const ALL: [Module; 3] = [Module::Upkeep, Module::Focus, Module::CalDav];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Module::Upkeep => "upkeep",
+            Module::Focus => "focus",
+            Module::CalDav => "caldav",
+        }
+    }
+}
+
+impl FromStr for Module {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Module::ALL
+            .into_iter()
+            .find(|module| module.name() == name)
+            .ok_or_else(|| anyhow!("There is no module called {:?}", name))
+    }
+}
+
+pub fn register(modules: &[Module], bootstrap_secret: Option<Arc<str>>) -> ModuleRegistration {
+    let mut sections = Vec::from([auth::register(bootstrap_secret), account::register()]);
+    for module in Module::ALL {
+        if !modules.contains(&module) {
+            continue;
+        }
+        sections.push(match module {
+            Module::Upkeep => upkeep::register(),
+            Module::Focus => upkeep::register_focus(),
+            Module::CalDav => upkeep::register_caldav(),
+        });
+    }
+    let sections = Box::from(sections);
     ModuleRegistration {
         default_module_name: "/core",
         sections,
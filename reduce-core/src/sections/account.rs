@@ -25,9 +25,12 @@ use anyhow::anyhow;
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use askama::DynTemplate;
 use axum::{
-    debug_handler, middleware,
+    debug_handler,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Extension, Router,
+    Extension, Form, Router,
 };
 use database::{
     fetch_email_for_login, fetch_password_hash_for_login, insert_email_password_login,
@@ -39,8 +42,12 @@ use sqlx::{Pool, Postgres};
 use templates::{CurrentEmailPasswordPartTemplate, IndexTemplate, NewEmailPasswordPartTemplate};
 
 use crate::{
-    error::AppResult, extensions::AuthorizedSession, extractors::csrf_form::CsrfForm,
+    error::{AppError, AppResult, ErrorKind},
+    extensions::AuthorizedSession,
+    extractors::valid_form::ValidForm,
+    flash::Flash,
     middleware::require_authentication::require_authentication,
+    validation::{Field, FormState, Validate},
 };
 
 use super::SectionRegistration;
@@ -48,6 +55,7 @@ use super::SectionRegistration;
 async fn index_template(
     pool: &Pool<Postgres>,
     session: AuthorizedSession,
+    form: FormState,
 ) -> AppResult<IndexTemplate> {
     let (current_methods, new_methods): (Rc<[_]>, Rc<[_]>) =
         match fetch_email_for_login(pool, session.account_id).await? {
@@ -61,6 +69,7 @@ async fn index_template(
             None => (
                 Rc::new([]),
                 Rc::new([Box::new(NewEmailPasswordPartTemplate {
+                    form,
                     authorized_session: session.clone(),
                 }) as Box<dyn DynTemplate>]),
             ),
@@ -77,7 +86,7 @@ async fn get_index(
     Extension(pool): Extension<Pool<Postgres>>,
     Extension(session): Extension<AuthorizedSession>,
 ) -> AppResult<IndexTemplate> {
-    index_template(&pool, session).await
+    index_template(&pool, session, FormState::default()).await
 }
 
 #[derive(Deserialize, Clone)]
@@ -85,11 +94,33 @@ struct PostPasswordForm {
     email: Arc<str>,
     password: Arc<str>,
 }
+
+impl Validate for PostPasswordForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([
+            Field::new("email", &self.email)
+                .required()
+                .max_length(255)
+                .email(),
+            Field::new("password", &self.password).required(),
+        ])
+    }
+}
+
 async fn post_password(
     Extension(pool): Extension<Pool<Postgres>>,
     Extension(session): Extension<AuthorizedSession>,
-    CsrfForm(PostPasswordForm { email, password }): CsrfForm<PostPasswordForm>,
-) -> AppResult<IndexTemplate> {
+    Extension(flash): Extension<Flash>,
+    ValidForm(form): ValidForm<PostPasswordForm>,
+) -> AppResult<Response> {
+    let PostPasswordForm { email, password } = match form {
+        Ok(form) => form,
+        Err(form) => {
+            let template = index_template(&pool, session, form).await?;
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, template).into_response());
+        }
+    };
+
     let argon_context = Argon2::default();
     let salt = SaltString::generate(&mut OsRng);
     let password_hash = argon_context
@@ -99,11 +130,20 @@ async fn post_password(
     insert_email_password_login(
         &pool,
         session.account_id,
-        &email,
+        email.trim(),
         password_hash.to_string().as_str(),
     )
-    .await?;
-    index_template(&pool, session).await
+    .await
+    .map_err(|error| match AppError::from(error) {
+        error if error.kind() == ErrorKind::Conflict => {
+            AppError::conflict("This email address is already in use")
+        }
+        error => error,
+    })?;
+    flash.success("You can now log in with this email address and password");
+    Ok(index_template(&pool, session, FormState::default())
+        .await?
+        .into_response())
 }
 
 #[derive(Deserialize, Clone)]
@@ -117,14 +157,15 @@ struct PutPasswordForm {
 async fn put_password(
     Extension(pool): Extension<Pool<Postgres>>,
     Extension(session): Extension<AuthorizedSession>,
-    CsrfForm(PutPasswordForm {
+    Extension(flash): Extension<Flash>,
+    Form(PutPasswordForm {
         current_password,
         confirm_current_password,
         new_password,
-    }): CsrfForm<PutPasswordForm>,
+    }): Form<PutPasswordForm>,
 ) -> AppResult<IndexTemplate> {
     if current_password != confirm_current_password {
-        return Err(anyhow!("Incorrect password").into());
+        return Err(AppError::validation("Incorrect password"));
     };
 
     let password_hash = fetch_password_hash_for_login(&pool, session.account_id).await?;
@@ -137,7 +178,10 @@ async fn put_password(
     );
 
     match result {
-        Err(_) => Ok(index_template(&pool, session).await?),
+        Err(_) => {
+            flash.error("The current password is incorrect, your password was not changed");
+            Ok(index_template(&pool, session, FormState::default()).await?)
+        }
         Ok(_) => {
             let salt = SaltString::generate(&mut OsRng);
             let password_hash = argon_context
@@ -149,7 +193,8 @@ async fn put_password(
                 password_hash.to_string().as_str(),
             )
             .await?;
-            Ok(index_template(&pool, session).await?)
+            flash.success("Your password has been changed");
+            Ok(index_template(&pool, session, FormState::default()).await?)
         }
     }
 }
@@ -162,7 +207,9 @@ pub fn register() -> SectionRegistration {
 
     SectionRegistration {
         router,
+        root_router: Router::new(),
         entry_page: "/account",
         title: "Account",
+        tasks: &[],
     }
 }
@@ -20,7 +20,10 @@ use std::{rc::Rc, sync::Arc};
 
 use askama::{DynTemplate, Template};
 
-use crate::extensions::{AuthorizedSession, Session};
+use crate::{
+    extensions::{AuthorizedSession, Session},
+    validation::FormState,
+};
 
 #[derive(Template)]
 #[template(path = "sections/account/index.html")]
@@ -40,5 +43,6 @@ pub struct CurrentEmailPasswordPartTemplate {
 #[derive(Template)]
 #[template(path = "sections/account/authenticate-methods/new-email-password.part.html")]
 pub struct NewEmailPasswordPartTemplate {
+    pub form: FormState,
     pub authorized_session: AuthorizedSession,
 }
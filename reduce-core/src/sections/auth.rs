@@ -19,25 +19,34 @@
 mod database;
 mod templates;
 
-use std::{env, sync::Arc};
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
 use argon2::{
-    password_hash::rand_core::{OsRng, RngCore},
-    Argon2, PasswordHash, PasswordVerifier,
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        SaltString,
+    },
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
 use askama_axum::IntoResponse;
 use axum::{
-    http::{HeaderMap, Response, StatusCode},
+    http::{header::SET_COOKIE, HeaderMap, Response, StatusCode},
     routing::{get, post},
     Extension, Form, Router,
 };
 use base64::{engine::general_purpose::STANDARD, Engine};
 use chrono::{Duration, Local};
+use once_cell::sync::Lazy;
 use serde::Deserialize;
 use sqlx::{Executor, Pool, Postgres};
 
-use crate::{error::AppResult, extensions::Session};
+use crate::{
+    cookies::{header_value, Cookies},
+    error::{AppError, AppResult, ErrorKind},
+    extensions::{CsrfToken, Session},
+    flash::Flash,
+};
 
 use self::{
     database::{
@@ -49,7 +58,16 @@ use self::{
 
 use super::SectionRegistration;
 
-async fn setup_session<'a, T>(pool: T, account_id: i32) -> Result<HeaderMap>
+/// Checked against when an email address has no account, so an unknown address takes as long to
+/// turn down as a wrong password.
+static DUMMY_PASSWORD_HASH: Lazy<String> = Lazy::new(|| {
+    Argon2::default()
+        .hash_password(b"dummy password", &SaltString::generate(&mut OsRng))
+        .expect("hashing a fixed password never fails")
+        .to_string()
+});
+
+async fn setup_session<'a, T>(pool: T, cookies: &Cookies, account_id: i32) -> Result<HeaderMap>
 where
     T: Executor<'a, Database = Postgres>,
 {
@@ -62,21 +80,24 @@ where
     let session_token = STANDARD.encode(session_token_bytes);
     let csrf_token = STANDARD.encode(csrf_token_bytes);
 
-    let expires_at = Local::now().naive_local() + Duration::days(1);
+    let expires_at = Local::now().naive_local() + Duration::from_std(cookies.session_lifetime())?;
 
     create_session(pool, account_id, &session_token, expires_at, &csrf_token).await?;
 
     let mut redirect_headers = HeaderMap::new();
     redirect_headers.insert("HX-Location", "/".parse()?);
-    redirect_headers.insert(
-        "Set-Cookie",
-        format!("session_token={}; Path=/; HttpOnly; Secure", session_token).parse()?,
-    );
+    redirect_headers.insert(SET_COOKIE, header_value(&cookies.session(session_token))?);
     Ok(redirect_headers)
 }
 
-pub async fn get_login(Extension(session): Extension<Session>) -> AppResult<impl IntoResponse> {
-    Ok(LoginTemplate { session })
+pub async fn get_login(
+    Extension(session): Extension<Session>,
+    Extension(CsrfToken(csrf_token)): Extension<CsrfToken>,
+) -> AppResult<impl IntoResponse> {
+    Ok(LoginTemplate {
+        csrf_token,
+        session,
+    })
 }
 
 #[derive(Deserialize)]
@@ -87,25 +108,61 @@ pub struct PostLoginForm {
 
 pub async fn post_login(
     Extension(session): Extension<Session>,
+    Extension(CsrfToken(csrf_token)): Extension<CsrfToken>,
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(cookies): Extension<Cookies>,
+    Extension(flash): Extension<Flash>,
     Form(PostLoginForm { email, password }): Form<PostLoginForm>,
 ) -> AppResult<impl IntoResponse> {
-    let account = fetch_email_login_details(&pool, &email).await?;
+    let account = match fetch_email_login_details(&pool, &email)
+        .await
+        .map_err(AppError::from)
+    {
+        Ok(account) => Some(account),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
 
-    let result = Argon2::default().verify_password(
-        password.as_bytes(),
-        &PasswordHash::new(&account.password_hash)
-            .map_err(|error| anyhow!("Error with generating hash: {:?}", error))?,
-    );
+    let password_hash = match &account {
+        Some(account) => &*account.password_hash,
+        None => DUMMY_PASSWORD_HASH.as_str(),
+    };
+    let is_valid = Argon2::default()
+        .verify_password(
+            password.as_bytes(),
+            &PasswordHash::new(password_hash)
+                .map_err(|error| anyhow!("Error with generating hash: {:?}", error))?,
+        )
+        .is_ok();
+    let result = account
+        .filter(|_| is_valid)
+        .map(|account| account.account_id);
 
     let none_headers = HeaderMap::new();
 
     match result {
-        Err(_) => Ok((none_headers, LoginTemplate { session })),
-        Ok(_) => {
-            let redirect_headers = setup_session(&pool, account.account_id).await?;
+        // Which of the two was wrong is not told, that would reveal who has an account.
+        None => {
+            flash.error("The email address or password is incorrect");
+            Ok((
+                none_headers,
+                LoginTemplate {
+                    csrf_token,
+                    session,
+                },
+            ))
+        }
+        Some(account_id) => {
+            let redirect_headers = setup_session(&pool, &cookies, account_id).await?;
+            flash.success("You are logged in");
 
-            Ok((redirect_headers, LoginTemplate { session }))
+            Ok((
+                redirect_headers,
+                LoginTemplate {
+                    csrf_token,
+                    session,
+                },
+            ))
         }
     }
 }
@@ -113,13 +170,17 @@ pub async fn post_login(
 pub async fn post_logout(
     Extension(session): Extension<Session>,
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(cookies): Extension<Cookies>,
+    Extension(flash): Extension<Flash>,
 ) -> AppResult<Response<String>> {
     Ok(match session {
         Session::Authenticated { session_id, .. } => {
             delete_session(&pool, session_id).await?;
+            flash.success("You are logged out");
             Response::builder()
                 .status(StatusCode::FOUND)
                 .header("Location", "/")
+                .header(SET_COOKIE, header_value(&cookies.session_removal())?)
                 .body("Redirecting".into())?
         }
         _ => Response::builder()
@@ -128,8 +189,14 @@ pub async fn post_logout(
     })
 }
 
-pub async fn get_bootstrap(Extension(session): Extension<Session>) -> impl IntoResponse {
-    BootstrapTemplate { session }
+pub async fn get_bootstrap(
+    Extension(session): Extension<Session>,
+    Extension(CsrfToken(csrf_token)): Extension<CsrfToken>,
+) -> impl IntoResponse {
+    BootstrapTemplate {
+        csrf_token,
+        session,
+    }
 }
 
 #[derive(Deserialize)]
@@ -137,41 +204,63 @@ pub struct PostBootstrapForm {
     bootstrap_secret: Arc<str>,
 }
 
+/// Without one, accounts can't be bootstrapped at all.
+#[derive(Clone)]
+pub struct BootstrapSecret(Option<Arc<str>>);
+
 pub async fn post_bootstrap(
+    Extension(BootstrapSecret(secret)): Extension<BootstrapSecret>,
     Extension(session): Extension<Session>,
+    Extension(CsrfToken(csrf_token)): Extension<CsrfToken>,
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(cookies): Extension<Cookies>,
     Form(PostBootstrapForm { bootstrap_secret }): Form<PostBootstrapForm>,
 ) -> AppResult<impl IntoResponse> {
-    let var = match env::var("REDUCE_BOOTSTRAP_SECRET") {
-        Ok(var) => var,
-        Err(e) => return Err(e.into()),
+    let Some(secret) = secret else {
+        return Err(AppError::forbidden(
+            "This server has no bootstrap secret configured",
+        ));
     };
 
-    if *var != *bootstrap_secret {
-        return Err(anyhow!("Given secret does not match environment secret").into());
+    if *secret != *bootstrap_secret {
+        return Err(AppError::unauthorized(
+            "Given secret does not match the configured secret",
+        ));
     };
 
     if fetch_bootstrap_secret_exists(&pool, &bootstrap_secret).await? {
-        return Err(anyhow!("You cannot reuse a secret").into());
+        return Err(AppError::conflict("You cannot reuse a secret"));
     };
 
     let BootstrapSecretResult { account_id } =
         insert_bootstrap_secret(&pool, &bootstrap_secret).await?;
 
-    let header_map = setup_session(&pool, account_id).await?;
+    let header_map = setup_session(&pool, &cookies, account_id).await?;
 
-    Ok((header_map, BootstrapTemplate { session }))
+    Ok((
+        header_map,
+        BootstrapTemplate {
+            csrf_token,
+            session,
+        },
+    ))
 }
 
-pub fn register() -> SectionRegistration {
+pub fn register(bootstrap_secret: Option<Arc<str>>) -> SectionRegistration {
+    // Hashed up front, otherwise the first unknown address would take longer than the rest.
+    Lazy::force(&DUMMY_PASSWORD_HASH);
+
     let router = Router::new()
         .route("/auth/login", get(get_login).post(post_login))
         .route("/auth/logout", post(post_logout))
-        .route("/auth/bootstrap", get(get_bootstrap).post(post_bootstrap));
+        .route("/auth/bootstrap", get(get_bootstrap).post(post_bootstrap))
+        .layer(Extension(BootstrapSecret(bootstrap_secret)));
 
     SectionRegistration {
         router,
+        root_router: Router::new(),
         entry_page: "",
         title: "",
+        tasks: &[],
     }
 }
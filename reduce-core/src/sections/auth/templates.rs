@@ -18,16 +18,20 @@
 
 use askama::Template;
 
+use std::sync::Arc;
+
 use crate::extensions::Session;
 
 #[derive(Template)]
 #[template(path = "sections/auth/login.html")]
 pub struct LoginTemplate {
+    pub csrf_token: Arc<str>,
     pub session: Session,
 }
 
 #[derive(Template)]
 #[template(path = "sections/auth/bootstrap.html")]
 pub struct BootstrapTemplate {
+    pub csrf_token: Arc<str>,
     pub session: Session,
 }
@@ -16,32 +16,174 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::time::Duration;
+
 use axum::{
     middleware,
-    routing::{delete, get, post},
-    Router,
+    response::Redirect,
+    routing::{any, delete, get, post},
+    Extension, Router,
 };
 
+use chrono::{Local, NaiveTime};
+use sqlx::{Pool, Postgres};
+
 use crate::middleware::require_authentication::require_authentication;
 
-use self::handler::{delete_item, get_index, patch_item, post_complete, post_index};
+use self::caldav::{
+    caldav_calendar, caldav_item, caldav_principal, prune_sync_changes, VerifiedLogins,
+};
+use self::handler::{
+    delete_item, delete_step, get_catch_up, get_columns, get_export_csv, get_export_json, get_feed,
+    get_focus, get_forecast, get_households, get_import, get_index, get_level, get_settings,
+    patch_item, post_accept_household_invite, post_capacity, post_catch_up, post_complete,
+    post_cooldown, post_decline_household_invite, post_effort, post_feed, post_focus_complete,
+    post_focus_toggle_step, post_household, post_household_member, post_import,
+    post_import_confirm, post_index, post_item_household, post_leave_household, post_level,
+    post_move_up_step, post_pause, post_pause_item, post_resume, post_resume_item,
+    post_resume_policy, post_step, post_toggle_step, post_window, settle_ended_pauses,
+};
 
 use super::SectionRegistration;
 
+mod caldav;
 mod database;
 mod handler;
+mod ical;
+mod migrate;
+mod schedule;
 mod templates;
+mod transfer;
 
 pub fn register() -> SectionRegistration {
     let router = Router::new()
         .route("/upkeep", get(get_index).post(post_index))
+        .route("/upkeep/columns", get(get_columns))
         .route("/upkeep/complete/:id", post(post_complete))
         .route("/upkeep/:id", delete(delete_item).patch(patch_item))
-        .layer(middleware::from_fn(require_authentication));
+        .route("/upkeep/:id/cooldown", post(post_cooldown))
+        .route("/upkeep/:id/effort", post(post_effort))
+        .route("/upkeep/:id/pause", post(post_pause_item))
+        .route("/upkeep/:id/window", post(post_window))
+        .route("/upkeep/:id/resume", post(post_resume_item))
+        .route("/upkeep/:id/resume-policy", post(post_resume_policy))
+        .route("/upkeep/:id/household", post(post_item_household))
+        .route("/upkeep/:id/steps", post(post_step))
+        .route("/upkeep/:id/steps/:step_id", delete(delete_step))
+        .route("/upkeep/:id/steps/:step_id/toggle", post(post_toggle_step))
+        .route("/upkeep/:id/steps/:step_id/up", post(post_move_up_step))
+        .route("/upkeep/catch-up", get(get_catch_up).post(post_catch_up))
+        .route("/upkeep/forecast", get(get_forecast))
+        .route("/upkeep/forecast/level", get(get_level).post(post_level))
+        .route("/upkeep/export.csv", get(get_export_csv))
+        .route("/upkeep/export.json", get(get_export_json))
+        .route("/upkeep/import", get(get_import).post(post_import))
+        .route("/upkeep/import/confirm", post(post_import_confirm))
+        .route(
+            "/upkeep/households",
+            get(get_households).post(post_household),
+        )
+        .route(
+            "/upkeep/households/:id/members",
+            post(post_household_member),
+        )
+        .route(
+            "/upkeep/households/:id/accept",
+            post(post_accept_household_invite),
+        )
+        .route(
+            "/upkeep/households/:id/decline",
+            post(post_decline_household_invite),
+        )
+        .route("/upkeep/households/:id/leave", post(post_leave_household))
+        .route("/upkeep/settings", get(get_settings))
+        .route("/upkeep/settings/capacity", post(post_capacity))
+        .route("/upkeep/settings/pause", post(post_pause))
+        .route("/upkeep/settings/resume", post(post_resume))
+        .route("/upkeep/settings/feed", post(post_feed))
+        .layer(middleware::from_fn(require_authentication))
+        // Calendar apps can't log in, the secret token in the URL is the authentication.
+        .route("/upkeep/feed/:feed_token/upkeep.ics", get(get_feed));
 
     SectionRegistration {
         router,
+        root_router: Router::new(),
         entry_page: "/upkeep",
         title: "Upkeep",
+        tasks: &[spawn_pause_settling],
+    }
+}
+
+pub fn register_focus() -> SectionRegistration {
+    let router = Router::new()
+        .route("/upkeep/focus", get(get_focus))
+        .route("/upkeep/focus/complete/:id", post(post_focus_complete))
+        .route(
+            "/upkeep/focus/:id/steps/:step_id/toggle",
+            post(post_focus_toggle_step),
+        )
+        .layer(middleware::from_fn(require_authentication));
+
+    SectionRegistration {
+        router,
+        root_router: Router::new(),
+        entry_page: "/upkeep/focus",
+        title: "Focus",
+        tasks: &[],
+    }
+}
+
+/// CalDAV clients authenticate with HTTP basic authentication instead of a session, and aren't
+/// linked from the navigation.
+pub fn register_caldav() -> SectionRegistration {
+    let router = Router::new()
+        .route("/caldav", any(caldav_principal))
+        .route("/caldav/", any(caldav_principal))
+        .route("/caldav/upkeep", any(caldav_calendar))
+        .route("/caldav/upkeep/", any(caldav_calendar))
+        .route("/caldav/upkeep/:resource", any(caldav_item))
+        .layer(Extension(VerifiedLogins::default()));
+    // Lets CalDAV clients find the server from just the host name, see RFC 6764.
+    let root_router = Router::new().route(
+        "/.well-known/caldav",
+        any(|| async { Redirect::permanent("/core/caldav/") }),
+    );
+
+    SectionRegistration {
+        router,
+        root_router,
+        entry_page: "",
+        title: "CalDAV",
+        tasks: &[spawn_change_pruning],
     }
 }
+
+/// Keeps the CalDAV change log from growing forever, when the server starts and then every day.
+fn spawn_change_pruning(pool: Pool<Postgres>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(error) = prune_sync_changes(&pool).await {
+                tracing::error!("Could not prune upkeep changes: {:?}", error);
+            }
+            tokio::time::sleep(Duration::from_secs(24 * 60 * 60)).await;
+        }
+    });
+}
+
+/// Settles pauses that ran out when the server starts, and again right after every midnight.
+fn spawn_pause_settling(pool: Pool<Postgres>) {
+    tokio::spawn(async move {
+        loop {
+            let now = Local::now();
+            if let Err(error) = settle_ended_pauses(&pool, now.date_naive()).await {
+                tracing::error!("Could not settle ended pauses: {:?}", error);
+            }
+            let midnight = now.date_naive().succ_opt().unwrap_or(now.date_naive());
+            let until_midnight = (midnight.and_time(NaiveTime::MIN) - now.naive_local())
+                .to_std()
+                .unwrap_or_default();
+            // Also checked every hour, a clock change shouldn't hold it off for a day.
+            tokio::time::sleep(until_midnight.min(Duration::from_secs(60 * 60))).await;
+        }
+    });
+}
@@ -0,0 +1,562 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+mod xml;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::anyhow;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use askama_axum::IntoResponse;
+use axum::{
+    extract::Path,
+    http::{
+        header::{
+            ALLOW, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, WWW_AUTHENTICATE,
+        },
+        HeaderMap, HeaderName, Method, StatusCode,
+    },
+    response::Response,
+    Extension,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    error::AppResult,
+    etag::{etag_matches, is_unchanged},
+};
+
+use self::xml::{
+    escape, parse_propfind, parse_report, Multistatus, Prop, Report, CALDAV, CALENDARSERVER, DAV,
+};
+
+use super::{
+    database::{
+        compact_upkeep_changes, complete_upkeep_item, delete_upkeep_item, fetch_caldav_login,
+        fetch_upkeep_changes_since, fetch_upkeep_sync_floor, fetch_upkeep_sync_revision,
+        patch_description_upkeep_item, patch_due_date_upkeep_item, prune_upkeep_changes,
+        FetchUpkeepItem,
+    },
+    handler::fetch_assigned_items,
+    ical::{etag, parse_todo, render_todo},
+};
+
+const ROOT: &str = "/core/caldav/";
+const CALENDAR: &str = "/core/caldav/upkeep/";
+const ALLOWED: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+/// As long as the description of an item may be.
+const MAX_SUMMARY_LENGTH: usize = 255;
+
+fn item_href(id: i32) -> String {
+    format!("{}upkeep-{}.ics", CALENDAR, id)
+}
+
+/// Accepts both a bare resource name and a full href, clients send either.
+fn parse_item_href(href: &str) -> Option<i32> {
+    href.rsplit('/')
+        .next()?
+        .strip_prefix("upkeep-")?
+        .strip_suffix(".ics")?
+        .parse()
+        .ok()
+}
+
+fn sync_token(revision: i64) -> String {
+    format!("urn:reduce:upkeep-sync:{}", revision)
+}
+
+fn parse_sync_token(sync_token: &str) -> Option<i64> {
+    sync_token
+        .strip_prefix("urn:reduce:upkeep-sync:")?
+        .parse()
+        .ok()
+}
+
+/// The revision to list the changes since. Tokens from before the oldest change that is still
+/// kept, or from the future, can't be answered.
+fn sync_since(sync_token: &str, floor: i64, revision: i64) -> Option<i64> {
+    parse_sync_token(sync_token).filter(|since| (floor..=revision).contains(since))
+}
+
+const VERIFICATION_LIFETIME: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Clients send the password with every request, and verifying it with Argon2 each time makes a
+/// sync crawl. Successful verifications are kept for a few minutes, under a hash of the password
+/// together with the password hash it was checked against, so changing the password ends them.
+#[derive(Clone, Default)]
+pub struct VerifiedLogins(Arc<Mutex<HashMap<[u8; 32], Instant>>>);
+
+impl VerifiedLogins {
+    fn key(password_hash: &str, password: &str) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(password_hash)
+            .chain_update(":")
+            .chain_update(password)
+            .finalize()
+            .into()
+    }
+
+    fn contains(&self, password_hash: &str, password: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&Self::key(password_hash, password))
+            .is_some_and(|verified_at| verified_at.elapsed() < VERIFICATION_LIFETIME)
+    }
+
+    fn insert(&self, password_hash: &str, password: &str) {
+        let mut verified = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        verified.retain(|_, verified_at| verified_at.elapsed() < VERIFICATION_LIFETIME);
+        verified.insert(Self::key(password_hash, password), Instant::now());
+    }
+}
+
+async fn authenticate(
+    pool: &Pool<Postgres>,
+    verified: &VerifiedLogins,
+    headers: &HeaderMap,
+) -> AppResult<Option<i32>> {
+    let credentials = match headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok())
+    {
+        Some(credentials) => credentials,
+        None => return Ok(None),
+    };
+    let (email, password) = match credentials.split_once(':') {
+        Some(credentials) => credentials,
+        None => return Ok(None),
+    };
+    let login = match fetch_caldav_login(pool, email).await? {
+        Some(login) => login,
+        None => return Ok(None),
+    };
+
+    if verified.contains(&login.password_hash, password) {
+        return Ok(Some(login.account_id));
+    }
+    let password_hash = PasswordHash::new(&login.password_hash)
+        .map_err(|error| anyhow!("Error with generating hash: {:?}", error))?;
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &password_hash)
+        .is_err()
+    {
+        return Ok(None);
+    }
+    verified.insert(&login.password_hash, password);
+    Ok(Some(login.account_id))
+}
+
+const CHANGE_RETENTION_DAYS: i64 = 90;
+
+/// Clients that haven't synced for longer than the retention start over with a full sync.
+pub async fn prune_sync_changes(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    compact_upkeep_changes(pool).await?;
+    let before = Utc::now().naive_utc() - Duration::days(CHANGE_RETENTION_DAYS);
+    prune_upkeep_changes(pool, &before).await?;
+    Ok(())
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, r#"Basic realm="Reduce", charset="UTF-8""#)],
+    )
+        .into_response()
+}
+
+fn options() -> Response {
+    (
+        StatusCode::OK,
+        [
+            (ALLOW, ALLOWED),
+            (HeaderName::from_static("dav"), "1, 3, calendar-access"),
+        ],
+    )
+        .into_response()
+}
+
+fn multistatus(body: String) -> Response {
+    (
+        StatusCode::MULTI_STATUS,
+        [(CONTENT_TYPE, "application/xml; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+/// Tokens the server never handed out, or from before the changes that are kept, see RFC 6578.
+fn invalid_sync_token() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        [(CONTENT_TYPE, "application/xml; charset=utf-8")],
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><d:error xmlns:d="{}"><d:valid-sync-token/></d:error>"#,
+            DAV
+        ),
+    )
+        .into_response()
+}
+
+fn is_deep(headers: &HeaderMap) -> bool {
+    headers
+        .get("Depth")
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|depth| depth.trim() != "0")
+}
+
+/// Items in the calendar are those assigned to the account, paused items are left out until
+/// they are resumed.
+async fn calendar_items(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+) -> AppResult<Box<[FetchUpkeepItem]>> {
+    Ok(fetch_assigned_items(pool, account_id)
+        .await?
+        .iter()
+        .filter(|item| item.paused_since.is_none())
+        .cloned()
+        .collect())
+}
+
+fn principal_props() -> Vec<Prop> {
+    let href = format!("<d:href>{}</d:href>", ROOT);
+    vec![
+        Prop::new(DAV, "resourcetype", "<d:collection/><d:principal/>"),
+        Prop::new(DAV, "displayname", "Reduce"),
+        Prop::new(DAV, "current-user-principal", href.clone()),
+        Prop::new(DAV, "principal-URL", href.clone()),
+        Prop::new(CALDAV, "calendar-home-set", href),
+    ]
+}
+
+fn calendar_props(revision: i64) -> Vec<Prop> {
+    let sync_token = escape(&sync_token(revision));
+    vec![
+        Prop::new(DAV, "resourcetype", "<d:collection/><c:calendar/>"),
+        Prop::new(DAV, "displayname", "Upkeep"),
+        Prop::new(
+            DAV,
+            "current-user-principal",
+            format!("<d:href>{}</d:href>", ROOT),
+        ),
+        Prop::new(
+            CALDAV,
+            "supported-calendar-component-set",
+            r#"<c:comp name="VTODO"/>"#,
+        ),
+        Prop::new(
+            DAV,
+            "supported-report-set",
+            concat!(
+                "<d:supported-report><d:report><c:calendar-query/></d:report></d:supported-report>",
+                "<d:supported-report><d:report><c:calendar-multiget/></d:report></d:supported-report>",
+                "<d:supported-report><d:report><d:sync-collection/></d:report></d:supported-report>",
+            ),
+        ),
+        Prop::new(DAV, "sync-token", sync_token.clone()),
+        Prop::new(CALENDARSERVER, "getctag", sync_token),
+    ]
+}
+
+fn item_props(item: &FetchUpkeepItem) -> Vec<Prop> {
+    let calendar = render_todo(item);
+    vec![
+        Prop::new(DAV, "resourcetype", ""),
+        Prop::new(DAV, "getetag", escape(&etag(&calendar))),
+        Prop::new(
+            DAV,
+            "getcontenttype",
+            "text/calendar; charset=utf-8; component=VTODO",
+        ),
+        Prop::expensive(CALDAV, "calendar-data", escape(&calendar)),
+    ]
+}
+
+pub async fn caldav_principal(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(verified): Extension<VerifiedLogins>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> AppResult<Response> {
+    if method == Method::OPTIONS {
+        return Ok(options());
+    }
+    let account_id = match authenticate(&pool, &verified, &headers).await? {
+        Some(account_id) => account_id,
+        None => return Ok(unauthorized()),
+    };
+
+    match method.as_str() {
+        "PROPFIND" => {
+            let request = match parse_propfind(&body) {
+                Ok(request) => request,
+                Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            };
+            let mut response = Multistatus::default();
+            response.push_props(ROOT, &principal_props(), &request);
+            if is_deep(&headers) {
+                let revision = fetch_upkeep_sync_revision(&pool, account_id).await?;
+                response.push_props(CALENDAR, &calendar_props(revision), &request);
+            }
+            Ok(multistatus(response.finish(None)))
+        }
+        _ => Ok((
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(ALLOW, "OPTIONS, PROPFIND")],
+        )
+            .into_response()),
+    }
+}
+
+pub async fn caldav_calendar(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(verified): Extension<VerifiedLogins>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> AppResult<Response> {
+    if method == Method::OPTIONS {
+        return Ok(options());
+    }
+    let account_id = match authenticate(&pool, &verified, &headers).await? {
+        Some(account_id) => account_id,
+        None => return Ok(unauthorized()),
+    };
+
+    // The revision is read first, so a change racing with this request is reported again on
+    // the next sync rather than missed.
+    let revision = fetch_upkeep_sync_revision(&pool, account_id).await?;
+    let items = calendar_items(&pool, account_id).await?;
+
+    match method.as_str() {
+        "PROPFIND" => {
+            let request = match parse_propfind(&body) {
+                Ok(request) => request,
+                Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            };
+            let mut response = Multistatus::default();
+            response.push_props(CALENDAR, &calendar_props(revision), &request);
+            if is_deep(&headers) {
+                for item in items.iter() {
+                    response.push_props(&item_href(item.id), &item_props(item), &request);
+                }
+            }
+            Ok(multistatus(response.finish(None)))
+        }
+        "REPORT" => {
+            let report = match parse_report(&body) {
+                Ok(report) => report,
+                Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            };
+            let mut response = Multistatus::default();
+            match report {
+                Report::CalendarQuery(request) => {
+                    for item in items.iter() {
+                        response.push_props(&item_href(item.id), &item_props(item), &request);
+                    }
+                    Ok(multistatus(response.finish(None)))
+                }
+                Report::CalendarMultiget(request, hrefs) => {
+                    for href in hrefs.iter() {
+                        match parse_item_href(href)
+                            .and_then(|id| items.iter().find(|item| item.id == id))
+                        {
+                            Some(item) => response.push_props(href, &item_props(item), &request),
+                            None => response.push_not_found(href),
+                        }
+                    }
+                    Ok(multistatus(response.finish(None)))
+                }
+                Report::SyncCollection(request, token) => {
+                    let changed: Box<[i32]> = match &*token {
+                        "" => items.iter().map(|item| item.id).collect(),
+                        token => {
+                            let floor = fetch_upkeep_sync_floor(&pool, account_id).await?;
+                            match sync_since(token, floor, revision) {
+                                Some(since) => fetch_upkeep_changes_since(&pool, account_id, since)
+                                    .await?
+                                    .iter()
+                                    .copied()
+                                    .collect(),
+                                None => return Ok(invalid_sync_token()),
+                            }
+                        }
+                    };
+                    for id in changed.iter() {
+                        match items.iter().find(|item| item.id == *id) {
+                            Some(item) => response.push_props(
+                                &item_href(item.id),
+                                &item_props(item),
+                                &request,
+                            ),
+                            None => response.push_not_found(&item_href(*id)),
+                        }
+                    }
+                    Ok(multistatus(response.finish(Some(&sync_token(revision)))))
+                }
+            }
+        }
+        _ => Ok((StatusCode::METHOD_NOT_ALLOWED, [(ALLOW, ALLOWED)]).into_response()),
+    }
+}
+
+fn precondition_failed(headers: &HeaderMap, etag: &str) -> bool {
+    let if_match = headers
+        .get(IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| !etag_matches(value, etag));
+    let if_none_match = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim() == "*");
+    if_match || if_none_match
+}
+
+pub async fn caldav_item(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(verified): Extension<VerifiedLogins>,
+    Path(resource): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> AppResult<Response> {
+    if method == Method::OPTIONS {
+        return Ok(options());
+    }
+    let account_id = match authenticate(&pool, &verified, &headers).await? {
+        Some(account_id) => account_id,
+        None => return Ok(unauthorized()),
+    };
+
+    let items = calendar_items(&pool, account_id).await?;
+    let item =
+        match parse_item_href(&resource).and_then(|id| items.iter().find(|item| item.id == id)) {
+            Some(item) => item,
+            // Upkeep items need a cooldown, which a task app has no notion of, so new items can
+            // only be created in Reduce itself.
+            None if method == Method::PUT => return Ok(StatusCode::FORBIDDEN.into_response()),
+            None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        };
+    let calendar = render_todo(item);
+    let etag = etag(&calendar);
+
+    match method.as_str() {
+        "GET" | "HEAD" => {
+            if is_unchanged(&headers, &etag) {
+                return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+            }
+            Ok((
+                [
+                    (CONTENT_TYPE, "text/calendar; charset=utf-8".to_string()),
+                    (ETAG, etag),
+                ],
+                calendar,
+            )
+                .into_response())
+        }
+        "PROPFIND" => {
+            let request = match parse_propfind(&body) {
+                Ok(request) => request,
+                Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            };
+            let mut response = Multistatus::default();
+            response.push_props(&item_href(item.id), &item_props(item), &request);
+            Ok(multistatus(response.finish(None)))
+        }
+        "PUT" => {
+            if precondition_failed(&headers, &etag) {
+                return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+            }
+            let todo = match parse_todo(&body) {
+                Some(todo) => todo,
+                None => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            };
+            let summary = todo.summary.as_deref().map(str::trim);
+            if summary.is_some_and(|summary| summary.chars().count() > MAX_SUMMARY_LENGTH) {
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+
+            if todo.is_completed {
+                complete_upkeep_item(&pool, item.id, account_id).await?;
+            } else {
+                if let Some(summary) = summary {
+                    if !summary.is_empty() && summary != &*item.description {
+                        patch_description_upkeep_item(&pool, item.id, account_id, summary).await?;
+                    }
+                }
+                if let Some(due) = todo.due.filter(|due| *due != item.latest_due()) {
+                    let due = due - Duration::days(item.window_days as i64);
+                    patch_due_date_upkeep_item(&pool, item.id, account_id, &due).await?;
+                }
+            }
+            // No ETag is returned, the stored item never matches the uploaded one exactly.
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        "DELETE" => {
+            if precondition_failed(&headers, &etag) {
+                return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+            }
+            delete_upkeep_item(&pool, item.id, account_id).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        _ => Ok((StatusCode::METHOD_NOT_ALLOWED, [(ALLOW, ALLOWED)]).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_hrefs_parse_back() {
+        assert_eq!(parse_item_href(&item_href(12)), Some(12));
+        assert_eq!(parse_item_href("upkeep-12.ics"), Some(12));
+        assert_eq!(parse_item_href("/core/caldav/upkeep/other-12.ics"), None);
+        assert_eq!(parse_item_href("upkeep-12.txt"), None);
+    }
+
+    #[test]
+    fn sync_tokens_are_answered_between_the_floor_and_the_revision() {
+        assert_eq!(sync_since(&sync_token(5), 3, 9), Some(5));
+        assert_eq!(sync_since(&sync_token(3), 3, 9), Some(3));
+        assert_eq!(sync_since(&sync_token(9), 3, 9), Some(9));
+    }
+
+    #[test]
+    fn pruned_future_and_foreign_sync_tokens_are_invalid() {
+        assert_eq!(sync_since(&sync_token(2), 3, 9), None);
+        assert_eq!(sync_since(&sync_token(10), 3, 9), None);
+        assert_eq!(sync_since("urn:other:5", 3, 9), None);
+        assert_eq!(sync_since("urn:reduce:upkeep-sync:five", 3, 9), None);
+    }
+}
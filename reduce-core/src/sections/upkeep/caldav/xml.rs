@@ -0,0 +1,246 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::{anyhow, Result};
+use roxmltree::{Document, Node};
+
+pub const DAV: &str = "DAV:";
+pub const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+pub const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct PropName {
+    pub namespace: Box<str>,
+    pub name: Box<str>,
+}
+
+impl PropName {
+    fn from_node(node: Node) -> Self {
+        Self {
+            namespace: node.tag_name().namespace().unwrap_or_default().into(),
+            name: node.tag_name().name().into(),
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        &*self.namespace == namespace && &*self.name == name
+    }
+}
+
+pub enum PropRequest {
+    AllProp,
+    Props(Box<[PropName]>),
+}
+
+pub enum Report {
+    CalendarQuery(PropRequest),
+    CalendarMultiget(PropRequest, Box<[Box<str>]>),
+    SyncCollection(PropRequest, Box<str>),
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name((namespace, name)))
+}
+
+fn prop_request(node: Node) -> PropRequest {
+    match child(node, DAV, "prop") {
+        Some(prop) => PropRequest::Props(
+            prop.children()
+                .filter(|child| child.is_element())
+                .map(PropName::from_node)
+                .collect(),
+        ),
+        None => PropRequest::AllProp,
+    }
+}
+
+/// An empty body asks for all properties, the same as `<allprop/>`.
+pub fn parse_propfind(body: &str) -> Result<PropRequest> {
+    if body.trim().is_empty() {
+        return Ok(PropRequest::AllProp);
+    }
+
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+    if !root.has_tag_name((DAV, "propfind")) {
+        return Err(anyhow!("Expected a propfind element"));
+    }
+    Ok(prop_request(root))
+}
+
+pub fn parse_report(body: &str) -> Result<Report> {
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+    let props = prop_request(root);
+
+    if root.has_tag_name((CALDAV, "calendar-query")) {
+        Ok(Report::CalendarQuery(props))
+    } else if root.has_tag_name((CALDAV, "calendar-multiget")) {
+        let hrefs = root
+            .children()
+            .filter(|child| child.has_tag_name((DAV, "href")))
+            .filter_map(|href| href.text())
+            .map(|href| href.trim().into())
+            .collect();
+        Ok(Report::CalendarMultiget(props, hrefs))
+    } else if root.has_tag_name((DAV, "sync-collection")) {
+        let sync_token = child(root, DAV, "sync-token")
+            .and_then(|sync_token| sync_token.text())
+            .unwrap_or_default()
+            .trim()
+            .into();
+        Ok(Report::SyncCollection(props, sync_token))
+    } else {
+        Err(anyhow!("Unsupported report {}", root.tag_name().name()))
+    }
+}
+
+/// A property value, `value` is the already serialized content of the element.
+pub struct Prop {
+    pub namespace: &'static str,
+    pub name: &'static str,
+    pub value: String,
+    /// Expensive properties are only returned when explicitly asked for.
+    pub is_expensive: bool,
+}
+
+impl Prop {
+    pub fn new(namespace: &'static str, name: &'static str, value: impl Into<String>) -> Self {
+        Self {
+            namespace,
+            name,
+            value: value.into(),
+            is_expensive: false,
+        }
+    }
+
+    pub fn expensive(
+        namespace: &'static str,
+        name: &'static str,
+        value: impl Into<String>,
+    ) -> Self {
+        Self {
+            is_expensive: true,
+            ..Self::new(namespace, name, value)
+        }
+    }
+}
+
+fn prefix(namespace: &str) -> &'static str {
+    match namespace {
+        CALDAV => "c",
+        CALENDARSERVER => "cs",
+        _ => "d",
+    }
+}
+
+pub struct Multistatus {
+    body: String,
+}
+
+impl Default for Multistatus {
+    fn default() -> Self {
+        Self {
+            body: format!(
+                r#"<?xml version="1.0" encoding="utf-8"?><d:multistatus xmlns:d="{}" xmlns:c="{}" xmlns:cs="{}">"#,
+                DAV, CALDAV, CALENDARSERVER
+            ),
+        }
+    }
+}
+
+impl Multistatus {
+    pub fn push_props(&mut self, href: &str, props: &[Prop], request: &PropRequest) {
+        self.body.push_str("<d:response><d:href>");
+        self.body.push_str(&escape(href));
+        self.body.push_str("</d:href>");
+
+        let mut found = String::new();
+        let mut missing = String::new();
+        let mut push_found = |prop: &Prop| {
+            let prefix = prefix(prop.namespace);
+            found.push_str(&format!(
+                "<{prefix}:{name}>{value}</{prefix}:{name}>",
+                name = prop.name,
+                value = prop.value
+            ));
+        };
+        match request {
+            PropRequest::AllProp => props
+                .iter()
+                .filter(|prop| !prop.is_expensive)
+                .for_each(push_found),
+            PropRequest::Props(names) => {
+                for name in names.iter() {
+                    match props.iter().find(|prop| name.is(prop.namespace, prop.name)) {
+                        Some(prop) => push_found(prop),
+                        None => missing.push_str(&format!(
+                            r#"<x:{} xmlns:x="{}"/>"#,
+                            name.name,
+                            escape(&name.namespace)
+                        )),
+                    }
+                }
+            }
+        }
+
+        if !found.is_empty() {
+            self.body.push_str("<d:propstat><d:prop>");
+            self.body.push_str(&found);
+            self.body
+                .push_str("</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>");
+        }
+        if !missing.is_empty() {
+            self.body.push_str("<d:propstat><d:prop>");
+            self.body.push_str(&missing);
+            self.body
+                .push_str("</d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>");
+        }
+        self.body.push_str("</d:response>");
+    }
+
+    pub fn push_not_found(&mut self, href: &str) {
+        self.body.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+            escape(href)
+        ));
+    }
+
+    pub fn finish(mut self, sync_token: Option<&str>) -> String {
+        if let Some(sync_token) = sync_token {
+            self.body.push_str(&format!(
+                "<d:sync-token>{}</d:sync-token>",
+                escape(sync_token)
+            ));
+        }
+        self.body.push_str("</d:multistatus>");
+        self.body
+    }
+}
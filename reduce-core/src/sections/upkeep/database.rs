@@ -19,14 +19,57 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use chrono::NaiveDate;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
 use sqlx::{query, query_as, Executor, Postgres};
 
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ResumePolicy {
+    Shift,
+    Keep,
+    Restart,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AssignmentMode {
+    Rotate,
+    Completer,
+}
+
+#[derive(Clone)]
 pub struct FetchUpkeepItem {
     pub id: i32,
     pub description: Arc<str>,
     pub cooldown_days: i32,
     pub due: NaiveDate,
+    pub paused_since: Option<NaiveDate>,
+    pub resume_policy: ResumePolicy,
+    pub effort_minutes: Option<i32>,
+    pub window_days: i32,
+    pub household_id: Option<i32>,
+    pub assignment_mode: AssignmentMode,
+    pub assigned_account_id: Option<i32>,
+    pub revision: i32,
+    /// In UTC.
+    pub updated_at: NaiveDateTime,
+}
+
+impl FetchUpkeepItem {
+    /// Shared items nobody is assigned to are up for grabs by every member.
+    pub fn is_assigned_to(&self, account_id: i32) -> bool {
+        self.household_id.is_none()
+            || self
+                .assigned_account_id
+                .is_none_or(|assigned| assigned == account_id)
+    }
+
+    pub fn latest_due(&self) -> NaiveDate {
+        self.due + Duration::days(self.window_days as i64)
+    }
 }
 
 pub async fn fetch_upkeep_items<'a, T>(
@@ -38,11 +81,25 @@ where
 {
     Ok(query_as! {
         FetchUpkeepItem,
-        "
-        SELECT id, description, cooldown_days, due FROM upkeep_items
-        WHERE account_id = $1
+        r#"
+        SELECT
+            id,
+            description,
+            cooldown_days,
+            due,
+            paused_since,
+            resume_policy AS "resume_policy: ResumePolicy",
+            effort_minutes,
+            window_days,
+            household_id,
+            assignment_mode AS "assignment_mode: AssignmentMode",
+            assigned_account_id,
+            revision,
+            updated_at
+        FROM upkeep_items
+        WHERE id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $1)
         ORDER BY due ASC
-        ",
+        "#,
         account_id
     }
     .fetch_all(executor)
@@ -50,43 +107,270 @@ where
     .into())
 }
 
+pub async fn fetch_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+) -> Result<Option<FetchUpkeepItem>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepItem,
+        r#"
+        SELECT
+            id,
+            description,
+            cooldown_days,
+            due,
+            paused_since,
+            resume_policy AS "resume_policy: ResumePolicy",
+            effort_minutes,
+            window_days,
+            household_id,
+            assignment_mode AS "assignment_mode: AssignmentMode",
+            assigned_account_id,
+            revision,
+            updated_at
+        FROM upkeep_items
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        "#,
+        id,
+        account_id
+    }
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Returns whether the item was inserted, it isn't when the account is not in the household.
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_upkeep_item<'a, T>(
     executor: T,
     account_id: i32,
     description: &str,
     cooldown_days: i32,
     due: &NaiveDate,
-) -> Result<()>
+    effort_minutes: Option<i32>,
+    window_days: i32,
+    household_id: Option<i32>,
+) -> Result<bool>
 where
     T: Executor<'a, Database = Postgres>,
 {
-    query! {
+    Ok(query! {
         "
-        INSERT INTO upkeep_items (account_id, description, cooldown_days, due)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO upkeep_items (
+            account_id,
+            description,
+            cooldown_days,
+            due,
+            effort_minutes,
+            window_days,
+            household_id,
+            assigned_account_id
+        )
+        SELECT
+            $1::INT,
+            $2::VARCHAR,
+            $3::INT,
+            $4::DATE,
+            $5::INT,
+            $6::INT,
+            $7::INT,
+            CASE WHEN $7::INT IS NULL THEN NULL ELSE $1::INT END
+        WHERE $7::INT IS NULL OR EXISTS (
+            SELECT 1 FROM household_members WHERE household_id = $7 AND account_id = $1
+        )
+        RETURNING id
         ",
         account_id,
         description,
         cooldown_days,
         due,
+        effort_minutes,
+        window_days,
+        household_id,
+    }
+    .fetch_optional(executor)
+    .await?
+    .is_some())
+}
+
+pub async fn complete_upkeep_item<'a, T>(executor: T, id: i32, account_id: i32) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        WITH completed AS (
+            UPDATE upkeep_items
+            SET due = CURRENT_DATE + cooldown_days * INTERVAL '1 day',
+                -- Rotation hands the item to whoever comes after the one who did it.
+                assigned_account_id = CASE
+                    WHEN household_id IS NULL OR assignment_mode = 'completer' THEN NULL
+                    ELSE COALESCE(
+                        (
+                            SELECT next.account_id FROM household_members next
+                            WHERE next.household_id = upkeep_items.household_id
+                            AND next.id > (
+                                SELECT completer.id FROM household_members completer
+                                WHERE completer.household_id = upkeep_items.household_id
+                                AND completer.account_id = $2
+                            )
+                            ORDER BY next.id ASC
+                            LIMIT 1
+                        ),
+                        (
+                            SELECT first.account_id FROM household_members first
+                            WHERE first.household_id = upkeep_items.household_id
+                            ORDER BY first.id ASC
+                            LIMIT 1
+                        )
+                    )
+                END
+            WHERE id = $1
+            AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+            RETURNING id
+        ), reset_steps AS (
+            UPDATE upkeep_steps
+            SET checked = FALSE
+            WHERE upkeep_item_id IN (SELECT id FROM completed)
+        )
+        INSERT INTO upkeep_completions (upkeep_item_id, account_id)
+        SELECT id, $2 FROM completed
+        ",
+        id,
+        account_id,
     }
     .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn complete_upkeep_item<'a, T>(executor: T, id: i32, account_id: i32) -> Result<()>
+pub struct FetchUpkeepCompletion {
+    pub upkeep_item_id: i32,
+    pub completed_at: NaiveDateTime,
+}
+
+/// Only the latest `per_item` completions of each item, oldest first.
+pub async fn fetch_upkeep_completions<'a, T>(
+    executor: T,
+    account_id: i32,
+    per_item: i64,
+) -> Result<Arc<[FetchUpkeepCompletion]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepCompletion,
+        r#"
+        SELECT upkeep_item_id AS "upkeep_item_id!", completed_at AS "completed_at!" FROM (
+            SELECT
+                upkeep_item_id,
+                completed_at,
+                ROW_NUMBER() OVER (
+                    PARTITION BY upkeep_item_id ORDER BY completed_at DESC
+                ) AS recency
+            FROM upkeep_completions
+            WHERE upkeep_item_id IN (
+                SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $1
+            )
+        ) AS recent
+        WHERE recency <= $2
+        ORDER BY completed_at ASC
+        "#,
+        account_id,
+        per_item,
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+/// The latest `limit` completions of one item, oldest first.
+pub async fn fetch_upkeep_item_completions<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    limit: i64,
+) -> Result<Arc<[FetchUpkeepCompletion]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepCompletion,
+        r#"
+        SELECT upkeep_item_id AS "upkeep_item_id!", completed_at AS "completed_at!" FROM (
+            SELECT upkeep_item_id, completed_at FROM upkeep_completions
+            WHERE upkeep_item_id = $1
+            AND upkeep_item_id IN (
+                SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2
+            )
+            ORDER BY completed_at DESC
+            LIMIT $3
+        ) AS recent
+        ORDER BY completed_at ASC
+        "#,
+        id,
+        account_id,
+        limit,
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+/// What the account already did, counted against the daily capacity.
+pub struct FetchUpkeepDone {
+    pub items: i64,
+    pub minutes: i64,
+}
+
+pub async fn fetch_upkeep_done_since<'a, T>(
+    executor: T,
+    account_id: i32,
+    since: &NaiveDateTime,
+) -> Result<FetchUpkeepDone>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepDone,
+        r#"
+        SELECT
+            COUNT(*) AS "items!",
+            COALESCE(SUM(upkeep_items.effort_minutes), 0) AS "minutes!"
+        FROM upkeep_completions
+        JOIN upkeep_items ON upkeep_items.id = upkeep_completions.upkeep_item_id
+        WHERE upkeep_completions.account_id = $1 AND upkeep_completions.completed_at >= $2
+        "#,
+        account_id,
+        since,
+    }
+    .fetch_one(executor)
+    .await?)
+}
+
+pub async fn patch_cooldown_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    cooldown_days: i32,
+) -> Result<()>
 where
     T: Executor<'a, Database = Postgres>,
 {
     query! {
         "
         UPDATE upkeep_items
-        SET due = CURRENT_DATE + cooldown_days * INTERVAL '1 day'
-        WHERE id = $1 AND account_id = $2
+        SET cooldown_days = $3
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
         ",
         id,
         account_id,
+        cooldown_days,
     }
     .execute(executor)
     .await?;
@@ -100,7 +384,8 @@ where
     query! {
         "
         DELETE FROM upkeep_items
-        WHERE id = $1 AND account_id = $2
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
         ",
         id,
         account_id,
@@ -123,7 +408,8 @@ where
         "
         UPDATE upkeep_items
         SET due = $3
-        WHERE id = $1 AND account_id = $2
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
         ",
         id,
         account_id,
@@ -133,3 +419,1141 @@ where
     .await?;
     Ok(())
 }
+
+pub async fn patch_window_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    due: &NaiveDate,
+    window_days: i32,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET due = $3, window_days = $4
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ",
+        id,
+        account_id,
+        due,
+        window_days,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn patch_resume_policy_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    resume_policy: ResumePolicy,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET resume_policy = $3
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ",
+        id,
+        account_id,
+        resume_policy as ResumePolicy,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn pause_upkeep_item<'a, T>(executor: T, id: i32, account_id: i32) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET paused_since = CURRENT_DATE
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        AND paused_since IS NULL
+        ",
+        id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn resume_upkeep_item<'a, T>(executor: T, id: i32, account_id: i32) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET
+            due = CASE resume_policy
+                WHEN 'shift' THEN due + (CURRENT_DATE - paused_since)
+                WHEN 'restart' THEN CURRENT_DATE + cooldown_days
+                ELSE due
+            END,
+            paused_since = NULL
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        AND paused_since IS NOT NULL
+        ",
+        id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct FetchUpkeepPause {
+    pub pause_start: NaiveDate,
+    pub pause_end: NaiveDate,
+}
+
+impl FetchUpkeepPause {
+    /// The day after the pause, or `today` when it is ended before then.
+    pub fn resumed_on(&self, today: NaiveDate) -> NaiveDate {
+        today.min(self.pause_end + Duration::days(1))
+    }
+}
+
+/// A pause that ended before `today` is left out, it is settled in the background.
+pub async fn fetch_upkeep_pause<'a, T>(
+    executor: T,
+    account_id: i32,
+    today: &NaiveDate,
+) -> Result<Option<FetchUpkeepPause>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepPause,
+        r#"
+        SELECT pause_start AS "pause_start!", pause_end AS "pause_end!" FROM upkeep_settings
+        WHERE account_id = $1 AND pause_start IS NOT NULL AND pause_end >= $2
+        "#,
+        account_id,
+        today,
+    }
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Clears the pause of the account and returns what it was. The row stays locked until the
+/// transaction ends, so a pause can only be taken once.
+pub async fn take_upkeep_pause<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Option<FetchUpkeepPause>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepPause,
+        r#"
+        UPDATE upkeep_settings
+        SET pause_start = NULL, pause_end = NULL
+        FROM (
+            SELECT account_id, pause_start, pause_end FROM upkeep_settings
+            WHERE account_id = $1 AND pause_start IS NOT NULL AND pause_end IS NOT NULL
+            FOR UPDATE
+        ) AS taken
+        WHERE upkeep_settings.account_id = taken.account_id
+        RETURNING taken.pause_start AS "pause_start!", taken.pause_end AS "pause_end!"
+        "#,
+        account_id,
+    }
+    .fetch_optional(executor)
+    .await?)
+}
+
+pub struct EndedUpkeepPause {
+    pub account_id: i32,
+    pub pause_start: NaiveDate,
+    pub pause_end: NaiveDate,
+}
+
+/// Like `take_upkeep_pause`, for every pause that ended before `today`.
+pub async fn take_ended_upkeep_pauses<'a, T>(
+    executor: T,
+    today: &NaiveDate,
+) -> Result<Box<[EndedUpkeepPause]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        EndedUpkeepPause,
+        r#"
+        UPDATE upkeep_settings
+        SET pause_start = NULL, pause_end = NULL
+        FROM (
+            SELECT account_id, pause_start, pause_end FROM upkeep_settings
+            WHERE pause_start IS NOT NULL AND pause_end < $1
+            FOR UPDATE
+        ) AS taken
+        WHERE upkeep_settings.account_id = taken.account_id
+        RETURNING
+            taken.account_id AS "account_id!",
+            taken.pause_start AS "pause_start!",
+            taken.pause_end AS "pause_end!"
+        "#,
+        today,
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub async fn upsert_upkeep_pause<'a, T>(
+    executor: T,
+    account_id: i32,
+    pause_start: &NaiveDate,
+    pause_end: &NaiveDate,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO upkeep_settings (account_id, pause_start, pause_end)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id) DO UPDATE
+        SET pause_start = EXCLUDED.pause_start, pause_end = EXCLUDED.pause_end
+        ",
+        account_id,
+        pause_start,
+        pause_end,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Items are shifted by the days from the start of the pause until `resumed_on`, whether the
+/// pause ran out or was ended early.
+pub async fn resume_upkeep_items_after_pause<'a, T>(
+    executor: T,
+    account_id: i32,
+    pause_start: &NaiveDate,
+    resumed_on: &NaiveDate,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET due = CASE resume_policy
+            WHEN 'shift' THEN due + ($3::DATE - $2::DATE)
+            WHEN 'restart' THEN $3::DATE + cooldown_days
+            ELSE due
+        END
+        WHERE account_id = $1 AND household_id IS NULL AND paused_since IS NULL
+        ",
+        account_id,
+        pause_start,
+        resumed_on,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn patch_effort_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    effort_minutes: Option<i32>,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET effort_minutes = $3
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ",
+        id,
+        account_id,
+        effort_minutes,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct FetchUpkeepCapacity {
+    pub daily_item_limit: Option<i32>,
+    pub daily_minute_limit: Option<i32>,
+}
+
+pub async fn fetch_upkeep_capacity<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<FetchUpkeepCapacity>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepCapacity,
+        "
+        SELECT daily_item_limit, daily_minute_limit FROM upkeep_settings
+        WHERE account_id = $1
+        ",
+        account_id
+    }
+    .fetch_optional(executor)
+    .await?
+    .unwrap_or_default())
+}
+
+pub async fn upsert_upkeep_capacity<'a, T>(
+    executor: T,
+    account_id: i32,
+    daily_item_limit: Option<i32>,
+    daily_minute_limit: Option<i32>,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO upkeep_settings (account_id, daily_item_limit, daily_minute_limit)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id) DO UPDATE
+        SET
+            daily_item_limit = EXCLUDED.daily_item_limit,
+            daily_minute_limit = EXCLUDED.daily_minute_limit
+        ",
+        account_id,
+        daily_item_limit,
+        daily_minute_limit,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct FetchUpkeepStep {
+    pub id: i32,
+    pub upkeep_item_id: i32,
+    pub description: Arc<str>,
+    pub checked: bool,
+}
+
+pub async fn fetch_upkeep_steps<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Arc<[FetchUpkeepStep]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepStep,
+        "
+        SELECT id, upkeep_item_id, description, checked FROM upkeep_steps
+        WHERE upkeep_item_id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $1)
+        ORDER BY upkeep_item_id ASC, position ASC
+        ",
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub async fn fetch_upkeep_item_steps<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+) -> Result<Arc<[FetchUpkeepStep]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchUpkeepStep,
+        "
+        SELECT id, upkeep_item_id, description, checked FROM upkeep_steps
+        WHERE upkeep_item_id = $1
+        AND upkeep_item_id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ORDER BY position ASC
+        ",
+        id,
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub async fn insert_upkeep_step<'a, T>(
+    executor: T,
+    upkeep_item_id: i32,
+    account_id: i32,
+    description: &str,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO upkeep_steps (upkeep_item_id, position, description)
+        SELECT id, COALESCE(
+            (SELECT MAX(position) + 1 FROM upkeep_steps WHERE upkeep_item_id = $1),
+            0
+        ), $3
+        FROM upkeep_items
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ",
+        upkeep_item_id,
+        account_id,
+        description,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn toggle_upkeep_step<'a, T>(
+    executor: T,
+    id: i32,
+    upkeep_item_id: i32,
+    account_id: i32,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_steps
+        SET checked = NOT checked
+        WHERE id = $1
+        AND upkeep_item_id = (
+            SELECT upkeep_item_id FROM upkeep_item_access
+            WHERE upkeep_item_id = $2 AND account_id = $3
+        )
+        ",
+        id,
+        upkeep_item_id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn move_up_upkeep_step<'a, T>(
+    executor: T,
+    id: i32,
+    upkeep_item_id: i32,
+    account_id: i32,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        WITH step AS (
+            SELECT upkeep_steps.id, upkeep_steps.position FROM upkeep_steps
+            JOIN upkeep_item_access
+            ON upkeep_item_access.upkeep_item_id = upkeep_steps.upkeep_item_id
+            WHERE upkeep_steps.id = $1
+            AND upkeep_item_access.upkeep_item_id = $2
+            AND upkeep_item_access.account_id = $3
+        ), previous AS (
+            SELECT upkeep_steps.id, upkeep_steps.position FROM upkeep_steps, step
+            WHERE upkeep_steps.upkeep_item_id = $2 AND upkeep_steps.position < step.position
+            ORDER BY upkeep_steps.position DESC
+            LIMIT 1
+        )
+        UPDATE upkeep_steps
+        SET position = CASE upkeep_steps.id
+            WHEN step.id THEN previous.position
+            ELSE step.position
+        END
+        FROM step, previous
+        WHERE upkeep_steps.id IN (step.id, previous.id)
+        ",
+        id,
+        upkeep_item_id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_upkeep_step<'a, T>(
+    executor: T,
+    id: i32,
+    upkeep_item_id: i32,
+    account_id: i32,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        DELETE FROM upkeep_steps
+        WHERE id = $1
+        AND upkeep_item_id = (
+            SELECT upkeep_item_id FROM upkeep_item_access
+            WHERE upkeep_item_id = $2 AND account_id = $3
+        )
+        ",
+        id,
+        upkeep_item_id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns whether the item was shared, it isn't when the account can't see the item or is not
+/// in the household.
+pub async fn share_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    household_id: Option<i32>,
+    assignment_mode: AssignmentMode,
+) -> Result<bool>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    // Whoever takes an item out of a household keeps it as a personal item.
+    Ok(query! {
+        "
+        UPDATE upkeep_items
+        SET account_id = CASE WHEN $3::INT IS NULL THEN $2 ELSE account_id END,
+            assigned_account_id = CASE
+                WHEN $3::INT IS NULL OR $4 = 'completer' THEN NULL
+                WHEN household_id = $3 AND assigned_account_id IS NOT NULL
+                    THEN assigned_account_id
+                ELSE $2
+            END,
+            household_id = $3,
+            assignment_mode = $4
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        AND ($3::INT IS NULL OR EXISTS (
+            SELECT 1 FROM household_members WHERE household_id = $3 AND account_id = $2
+        ))
+        RETURNING id
+        ",
+        id,
+        account_id,
+        household_id,
+        assignment_mode as AssignmentMode,
+    }
+    .fetch_optional(executor)
+    .await?
+    .is_some())
+}
+
+pub struct FetchHousehold {
+    pub id: i32,
+    pub name: Arc<str>,
+}
+
+pub async fn fetch_households<'a, T>(executor: T, account_id: i32) -> Result<Arc<[FetchHousehold]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchHousehold,
+        "
+        SELECT households.id, households.name FROM households
+        JOIN household_members ON household_members.household_id = households.id
+        WHERE household_members.account_id = $1
+        ORDER BY households.name ASC
+        ",
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub struct FetchHouseholdMember {
+    pub household_id: i32,
+    pub account_id: i32,
+    pub name: Arc<str>,
+}
+
+pub async fn fetch_household_members<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Arc<[FetchHouseholdMember]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchHouseholdMember,
+        r#"
+        SELECT
+            household_members.household_id,
+            household_members.account_id,
+            COALESCE(email_password_logins.email, 'Account #' || household_members.account_id)
+                AS "name!"
+        FROM household_members
+        LEFT JOIN email_password_logins
+        ON email_password_logins.account_id = household_members.account_id
+        WHERE household_members.household_id IN (
+            SELECT household_id FROM household_members WHERE account_id = $1
+        )
+        ORDER BY household_members.id ASC
+        "#,
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub async fn insert_household<'a, T>(executor: T, account_id: i32, name: &str) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        WITH household AS (
+            INSERT INTO households (name)
+            VALUES ($2)
+            RETURNING id
+        )
+        INSERT INTO household_members (household_id, account_id)
+        SELECT id, $1 FROM household
+        ",
+        account_id,
+        name,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn is_household_member<'a, T>(
+    executor: T,
+    household_id: i32,
+    account_id: i32,
+) -> Result<bool>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM household_members WHERE household_id = $1 AND account_id = $2
+        ) AS "is_member!"
+        "#,
+        household_id,
+        account_id,
+    }
+    .fetch_one(executor)
+    .await?
+    .is_member)
+}
+
+/// Does nothing when no account uses the email, so the inviting member can't tell whether it
+/// does.
+pub async fn insert_household_invite<'a, T>(
+    executor: T,
+    household_id: i32,
+    account_id: i32,
+    email: &str,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO household_invites (household_id, account_id, invited_by)
+        SELECT $1, email_password_logins.account_id, $2 FROM email_password_logins
+        WHERE email_password_logins.email = $3
+        AND EXISTS (
+            SELECT 1 FROM household_members WHERE household_id = $1 AND account_id = $2
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM household_members
+            WHERE household_id = $1 AND account_id = email_password_logins.account_id
+        )
+        ON CONFLICT (household_id, account_id) DO NOTHING
+        ",
+        household_id,
+        account_id,
+        email,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct FetchHouseholdInvite {
+    pub household_id: i32,
+    pub household: Arc<str>,
+    pub invited_by: Arc<str>,
+}
+
+pub async fn fetch_household_invites<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Arc<[FetchHouseholdInvite]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchHouseholdInvite,
+        r#"
+        SELECT
+            households.id AS household_id,
+            households.name AS household,
+            COALESCE(
+                email_password_logins.email,
+                'Account #' || household_invites.invited_by,
+                'Someone'
+            ) AS "invited_by!"
+        FROM household_invites
+        JOIN households ON households.id = household_invites.household_id
+        LEFT JOIN email_password_logins
+        ON email_password_logins.account_id = household_invites.invited_by
+        WHERE household_invites.account_id = $1
+        ORDER BY household_invites.id ASC
+        "#,
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+/// Returns whether there was an invite to accept.
+pub async fn accept_household_invite<'a, T>(
+    executor: T,
+    household_id: i32,
+    account_id: i32,
+) -> Result<bool>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        "
+        WITH accepted AS (
+            DELETE FROM household_invites
+            WHERE household_id = $1 AND account_id = $2
+            RETURNING household_id, account_id
+        ), inserted AS (
+            INSERT INTO household_members (household_id, account_id)
+            SELECT household_id, account_id FROM accepted
+            ON CONFLICT (household_id, account_id) DO NOTHING
+        )
+        SELECT household_id FROM accepted
+        ",
+        household_id,
+        account_id,
+    }
+    .fetch_optional(executor)
+    .await?
+    .is_some())
+}
+
+pub async fn delete_household_invite<'a, T>(
+    executor: T,
+    household_id: i32,
+    account_id: i32,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "DELETE FROM household_invites WHERE household_id = $1 AND account_id = $2",
+        household_id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn leave_household<'a, T>(executor: T, household_id: i32, account_id: i32) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    // Items assigned to the leaving member become up for grabs, and an empty household is
+    // removed so its items return to whoever created them.
+    query! {
+        "
+        WITH left_household AS (
+            DELETE FROM household_members
+            WHERE household_id = $1 AND account_id = $2
+            RETURNING household_id
+        ), unassigned AS (
+            UPDATE upkeep_items
+            SET assigned_account_id = NULL
+            WHERE household_id IN (SELECT household_id FROM left_household)
+            AND assigned_account_id = $2
+        )
+        DELETE FROM households
+        WHERE id IN (SELECT household_id FROM left_household)
+        AND NOT EXISTS (
+            SELECT 1 FROM household_members
+            WHERE household_id = $1 AND account_id <> $2
+        )
+        ",
+        household_id,
+        account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct FetchHouseholdCompletion {
+    pub household: Arc<str>,
+    pub description: Arc<str>,
+    pub completed_by: Arc<str>,
+    pub completed_at: NaiveDateTime,
+}
+
+pub async fn fetch_household_history<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Arc<[FetchHouseholdCompletion]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchHouseholdCompletion,
+        r#"
+        SELECT
+            households.name AS household,
+            upkeep_items.description,
+            COALESCE(
+                email_password_logins.email,
+                'Account #' || upkeep_completions.account_id,
+                'Someone'
+            ) AS "completed_by!",
+            upkeep_completions.completed_at
+        FROM upkeep_completions
+        JOIN upkeep_items ON upkeep_items.id = upkeep_completions.upkeep_item_id
+        JOIN households ON households.id = upkeep_items.household_id
+        JOIN household_members ON household_members.household_id = households.id
+        LEFT JOIN email_password_logins
+        ON email_password_logins.account_id = upkeep_completions.account_id
+        WHERE household_members.account_id = $1
+        ORDER BY upkeep_completions.completed_at DESC
+        LIMIT 50
+        "#,
+        account_id
+    }
+    .fetch_all(executor)
+    .await?
+    .into())
+}
+
+pub async fn fetch_upkeep_feed_token<'a, T>(
+    executor: T,
+    account_id: i32,
+) -> Result<Option<Arc<str>>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        "
+        SELECT feed_token FROM upkeep_settings
+        WHERE account_id = $1
+        ",
+        account_id
+    }
+    .fetch_optional(executor)
+    .await?
+    .and_then(|row| row.feed_token)
+    .map(Arc::from))
+}
+
+pub async fn upsert_upkeep_feed_token<'a, T>(
+    executor: T,
+    account_id: i32,
+    feed_token: &str,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO upkeep_settings (account_id, feed_token)
+        VALUES ($1, $2)
+        ON CONFLICT (account_id) DO UPDATE
+        SET feed_token = EXCLUDED.feed_token
+        ",
+        account_id,
+        feed_token,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_upkeep_feed_account<'a, T>(executor: T, feed_token: &str) -> Result<Option<i32>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        "
+        SELECT account_id FROM upkeep_settings
+        WHERE feed_token = $1
+        ",
+        feed_token
+    }
+    .fetch_optional(executor)
+    .await?
+    .map(|row| row.account_id))
+}
+
+pub async fn patch_description_upkeep_item<'a, T>(
+    executor: T,
+    id: i32,
+    account_id: i32,
+    description: &str,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        UPDATE upkeep_items
+        SET description = $3
+        WHERE id = $1
+        AND id IN (SELECT upkeep_item_id FROM upkeep_item_access WHERE account_id = $2)
+        ",
+        id,
+        account_id,
+        description,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct FetchCaldavLogin {
+    pub account_id: i32,
+    pub password_hash: Arc<str>,
+}
+
+pub async fn fetch_caldav_login<'a, T>(executor: T, email: &str) -> Result<Option<FetchCaldavLogin>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query_as! {
+        FetchCaldavLogin,
+        "
+        SELECT account_id, password_hash FROM email_password_logins
+        WHERE email = $1
+        ",
+        email
+    }
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Never below the sync floor, so the token stays valid after every change was pruned.
+pub async fn fetch_upkeep_sync_revision<'a, T>(executor: T, account_id: i32) -> Result<i64>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        r#"
+        SELECT GREATEST(
+            (SELECT MAX(id) FROM upkeep_changes WHERE account_id = $1),
+            (SELECT revision FROM upkeep_sync_floors WHERE account_id = $1),
+            0
+        ) AS "revision!"
+        "#,
+        account_id
+    }
+    .fetch_one(executor)
+    .await?
+    .revision)
+}
+
+/// Sync tokens below the floor may have missed changes that were pruned since.
+pub async fn fetch_upkeep_sync_floor<'a, T>(executor: T, account_id: i32) -> Result<i64>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        r#"
+        SELECT COALESCE(
+            (SELECT revision FROM upkeep_sync_floors WHERE account_id = $1),
+            0
+        ) AS "floor!"
+        "#,
+        account_id
+    }
+    .fetch_one(executor)
+    .await?
+    .floor)
+}
+
+/// Only the latest change of an item matters to a sync, so older ones can go at any time.
+pub async fn compact_upkeep_changes<'a, T>(executor: T) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        DELETE FROM upkeep_changes
+        WHERE id IN (
+            SELECT id FROM (
+                SELECT
+                    id,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY account_id, upkeep_item_id ORDER BY id DESC
+                    ) AS recency
+                FROM upkeep_changes
+            ) AS ranked
+            WHERE recency > 1
+        )
+        "
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Removes changes from before the given time, and raises the sync floor of their accounts past
+/// them.
+pub async fn prune_upkeep_changes<'a, T>(executor: T, before: &NaiveDateTime) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        WITH pruned AS (
+            DELETE FROM upkeep_changes
+            WHERE changed_at < $1
+            RETURNING account_id, id
+        )
+        INSERT INTO upkeep_sync_floors (account_id, revision)
+        SELECT account_id, MAX(id) FROM pruned
+        GROUP BY account_id
+        ON CONFLICT (account_id) DO UPDATE
+        SET revision = GREATEST(upkeep_sync_floors.revision, EXCLUDED.revision)
+        ",
+        before,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_upkeep_changes_since<'a, T>(
+    executor: T,
+    account_id: i32,
+    revision: i64,
+) -> Result<Arc<[i32]>>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    Ok(query! {
+        "
+        SELECT DISTINCT upkeep_item_id FROM upkeep_changes
+        WHERE account_id = $1 AND id > $2
+        ORDER BY upkeep_item_id ASC
+        ",
+        account_id,
+        revision,
+    }
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|row| row.upkeep_item_id)
+    .collect())
+}
+
+pub struct ImportUpkeepItem {
+    pub description: Arc<str>,
+    pub cooldown_days: i32,
+    pub due: NaiveDate,
+    pub paused_since: Option<NaiveDate>,
+    pub resume_policy: ResumePolicy,
+    pub effort_minutes: Option<i32>,
+    pub window_days: i32,
+    pub household_id: Option<i32>,
+    pub assignment_mode: AssignmentMode,
+    pub assigned_account_id: Option<i32>,
+}
+
+pub async fn import_upkeep_item<'a, T>(
+    executor: T,
+    account_id: i32,
+    item: &ImportUpkeepItem,
+) -> Result<()>
+where
+    T: Executor<'a, Database = Postgres>,
+{
+    query! {
+        "
+        INSERT INTO upkeep_items (
+            account_id,
+            description,
+            cooldown_days,
+            due,
+            paused_since,
+            resume_policy,
+            effort_minutes,
+            window_days,
+            household_id,
+            assignment_mode,
+            assigned_account_id
+        )
+        SELECT
+            $1::INT,
+            $2::VARCHAR,
+            $3::INT,
+            $4::DATE,
+            $5::DATE,
+            $6::VARCHAR,
+            $7::INT,
+            $8::INT,
+            $9::INT,
+            $10::VARCHAR,
+            $11::INT
+        WHERE $9::INT IS NULL OR EXISTS (
+            SELECT 1 FROM household_members WHERE household_id = $9 AND account_id = $1
+        )
+        ",
+        account_id,
+        &*item.description,
+        item.cooldown_days,
+        item.due,
+        item.paused_since,
+        item.resume_policy as ResumePolicy,
+        item.effort_minutes,
+        item.window_days,
+        item.household_id,
+        item.assignment_mode as AssignmentMode,
+        item.assigned_account_id,
+    }
+    .execute(executor)
+    .await?;
+    Ok(())
+}
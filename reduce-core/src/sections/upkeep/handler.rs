@@ -16,108 +16,574 @@
 * along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use askama_axum::IntoResponse;
-use axum::{extract::Path, Extension, Form};
-use chrono::{Duration, Local, NaiveDate};
+use axum::{
+    extract::Multipart,
+    extract::{Path, Query},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG},
+        HeaderMap, StatusCode,
+    },
+    response::Response,
+    Extension, Form,
+};
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use rand::{rngs::OsRng, RngCore};
 use serde::Deserialize;
 use sqlx::{Pool, Postgres};
 
-use crate::{error::AppResult, extensions::AuthorizedSession, extractors::csrf_form::CsrfForm};
+use crate::{
+    error::{AppError, AppResult},
+    etag::is_unchanged,
+    extensions::AuthorizedSession,
+    extractors::{hx_request::HxRequest, valid_form::ValidForm},
+    flash::Flash,
+    validation::{Field, FormState, Validate},
+};
 
 use super::{
     database::{
-        complete_upkeep_item, delete_upkeep_item, fetch_upkeep_items, insert_upkeep_item,
-        patch_due_date_upkeep_item,
+        accept_household_invite, complete_upkeep_item, delete_household_invite, delete_upkeep_item,
+        delete_upkeep_step, fetch_household_history, fetch_household_invites,
+        fetch_household_members, fetch_households, fetch_upkeep_capacity, fetch_upkeep_completions,
+        fetch_upkeep_done_since, fetch_upkeep_feed_account, fetch_upkeep_feed_token,
+        fetch_upkeep_item, fetch_upkeep_item_completions, fetch_upkeep_item_steps,
+        fetch_upkeep_items, fetch_upkeep_pause, fetch_upkeep_steps, import_upkeep_item,
+        insert_household, insert_household_invite, insert_upkeep_item, insert_upkeep_step,
+        is_household_member, leave_household, move_up_upkeep_step, patch_cooldown_upkeep_item,
+        patch_due_date_upkeep_item, patch_effort_upkeep_item, patch_resume_policy_upkeep_item,
+        patch_window_upkeep_item, pause_upkeep_item, resume_upkeep_item,
+        resume_upkeep_items_after_pause, share_upkeep_item, take_ended_upkeep_pauses,
+        take_upkeep_pause, toggle_upkeep_step, upsert_upkeep_capacity, upsert_upkeep_feed_token,
+        upsert_upkeep_pause, AssignmentMode, FetchUpkeepItem, FetchUpkeepPause, FetchUpkeepStep,
+        ResumePolicy,
+    },
+    ical::{etag, render_calendar, Component},
+    schedule::{
+        decode_changes, encode_changes, fit_capacity, focus_candidates, forecast, level_schedule,
+        plan_catch_up, suggest_cooldown, PlannedChange, SUGGESTION_COMPLETIONS,
+    },
+    templates::{
+        CatchUpTemplate, ColumnsPartTemplate, CreateFormPartTemplate, FocusTemplate,
+        ForecastTemplate, HouseholdsTemplate, ImportTemplate, IndexTemplate, ItemCardPartTemplate,
+        PartHousehold, PartHouseholdCompletion, PartHouseholdInvite, PartImportRow,
+        PartImportSummary, PartItem, PartSkippedItem, PartStep, SettingsTemplate,
+    },
+    transfer::{
+        check_rows, export_csv, export_json, parse_upload, Membership, ParsedRow, RowStatus, Source,
     },
-    templates::{IndexTemplate, PartItem},
 };
 
+/// Shifts the items of every pause that ended before `today`. Runs in the background, so reading
+/// pages never writes to the database.
+pub async fn settle_ended_pauses(pool: &Pool<Postgres>, today: NaiveDate) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+    for ended in take_ended_upkeep_pauses(&mut *transaction, &today)
+        .await?
+        .iter()
+    {
+        let pause = FetchUpkeepPause {
+            pause_start: ended.pause_start,
+            pause_end: ended.pause_end,
+        };
+        resume_upkeep_items_after_pause(
+            &mut *transaction,
+            ended.account_id,
+            &pause.pause_start,
+            &pause.resumed_on(today),
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn is_paused(pool: &Pool<Postgres>, account_id: i32, today: NaiveDate) -> AppResult<bool> {
+    let pause = fetch_upkeep_pause(pool, account_id, &today).await?;
+    Ok(matches!(pause, Some(pause) if pause.pause_start <= today))
+}
+
+fn relative_day(date: NaiveDate, today: NaiveDate) -> String {
+    match (date - today).num_days() {
+        -1 => "yesterday".into(),
+        0 => "today".into(),
+        1 => "tomorrow".into(),
+        difference if difference < 0 => format!("{} days ago", -difference),
+        difference => format!("in {} days", difference),
+    }
+}
+
+fn part_steps(steps: &[&FetchUpkeepStep]) -> Box<[PartStep]> {
+    steps
+        .iter()
+        .map(|step| PartStep {
+            id: step.id,
+            description: step.description.clone(),
+            checked: step.checked,
+        })
+        .collect()
+}
+
+fn part_item(item: &FetchUpkeepItem, today: NaiveDate, is_due: bool) -> PartItem {
+    let latest = item.latest_due();
+    let is_windowed = item.window_days > 0;
+    let due = match item.paused_since {
+        Some(paused_since) => format!("Paused since {}", paused_since),
+        None if !is_windowed => format!("Due {}", relative_day(item.due, today)),
+        None if item.due > today => format!(
+            "Available {}, due {}",
+            relative_day(item.due, today),
+            relative_day(latest, today)
+        ),
+        None if latest > today => format!("Available, due {}", relative_day(latest, today)),
+        None => format!("Urgent, due {}", relative_day(latest, today)),
+    }
+    .into();
+    PartItem {
+        id: item.id,
+        description: item.description.clone(),
+        due,
+        cooldown: format!("Cooldown: {} days", item.cooldown_days).into(),
+        effort: item
+            .effort_minutes
+            .map(|effort| format!("Effort: {} minutes", effort).into()),
+        suggested_cooldown: None,
+        render_complete: is_due,
+        is_available: is_due && is_windowed && latest > today,
+        is_urgent: is_due && is_windowed && latest <= today,
+        paused: item.paused_since.is_some(),
+        resume_policy: item.resume_policy,
+        steps: Box::from([]),
+        next_step: None,
+        steps_done: 0,
+        household_id: item.household_id,
+        assignment_mode: item.assignment_mode,
+        sharing: None,
+        form: FormState::default(),
+    }
+}
+
+fn with_steps(part: PartItem, steps: Box<[PartStep]>) -> PartItem {
+    PartItem {
+        next_step: steps.iter().find(|step| !step.checked).cloned(),
+        steps_done: steps.iter().filter(|step| step.checked).count(),
+        steps,
+        ..part
+    }
+}
+
+pub async fn fetch_assigned_items(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+) -> AppResult<Box<[FetchUpkeepItem]>> {
+    Ok(fetch_upkeep_items(pool, account_id)
+        .await?
+        .iter()
+        .filter(|item| item.is_assigned_to(account_id))
+        .cloned()
+        .collect())
+}
+
+async fn fetch_part_households(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+) -> AppResult<(Box<[PartHousehold]>, HashMap<i32, Arc<str>>)> {
+    let members = fetch_household_members(pool, account_id).await?;
+    let households = fetch_households(pool, account_id)
+        .await?
+        .iter()
+        .map(|household| PartHousehold {
+            id: household.id,
+            name: household.name.clone(),
+            members: members
+                .iter()
+                .filter(|member| member.household_id == household.id)
+                .map(|member| member.name.clone())
+                .collect(),
+        })
+        .collect();
+    let names = members
+        .iter()
+        .map(|member| (member.account_id, member.name.clone()))
+        .collect();
+    Ok((households, names))
+}
+
+fn sharing(
+    item: &FetchUpkeepItem,
+    households: &[PartHousehold],
+    names: &HashMap<i32, Arc<str>>,
+) -> Option<Arc<str>> {
+    let household = households
+        .iter()
+        .find(|household| Some(household.id) == item.household_id)?;
+    let assigned = match item.assigned_account_id.and_then(|id| names.get(&id)) {
+        Some(name) => format!("assigned to {}", name),
+        None => "anyone can do this".into(),
+    };
+    Some(format!("Shared with {}, {}", household.name, assigned).into())
+}
+
+async fn fetch_steps_by_item(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+) -> AppResult<HashMap<i32, Box<[PartStep]>>> {
+    let steps = fetch_upkeep_steps(pool, account_id).await?;
+    let mut grouped: HashMap<i32, Vec<&FetchUpkeepStep>> = HashMap::new();
+    for step in steps.iter() {
+        grouped.entry(step.upkeep_item_id).or_default().push(step);
+    }
+    Ok(grouped
+        .into_iter()
+        .map(|(id, steps)| (id, part_steps(&steps)))
+        .collect())
+}
+
 pub async fn get_index(
     Extension(authorized_session): Extension<AuthorizedSession>,
     Extension(pool): Extension<Pool<Postgres>>,
 ) -> AppResult<impl IntoResponse> {
-    let items = fetch_upkeep_items(&pool, authorized_session.account_id).await?;
-    let mut split_at = 0;
+    index_template(authorized_session, pool, FormState::default()).await
+}
+
+pub async fn get_columns(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<impl IntoResponse> {
     let today = Local::now().date_naive();
-    let items: Box<_> = items
-        .iter()
-        .map(|item| {
-            let is_due = item.due <= today;
+    let is_paused = is_paused(&pool, authorized_session.account_id, today).await?;
+    columns_template(&authorized_session, &pool, today, is_paused).await
+}
 
-            if is_due {
-                split_at += 1;
-            };
-            let due_difference = (item.due - today).num_days();
-            let due = match due_difference {
-                -1 => "Due yesterday".into(),
-                0 => "Due today".into(),
-                1 => "Due tomorrow".into(),
-                due if due < 0 => format!("Due {} days ago", -due).into(),
-                due => format!("Due in {} days", due).into(),
-            };
+/// After a change that can move items between the columns. htmx requests only get the columns
+/// back, swapped in place of the due column, whatever element the request targeted.
+async fn updated_columns(
+    authorized_session: AuthorizedSession,
+    pool: Pool<Postgres>,
+    HxRequest(is_htmx): HxRequest,
+) -> AppResult<Response> {
+    if !is_htmx {
+        return Ok(
+            index_template(authorized_session, pool, FormState::default())
+                .await?
+                .into_response(),
+        );
+    }
+    let today = Local::now().date_naive();
+    let is_paused = is_paused(&pool, authorized_session.account_id, today).await?;
+    Ok((
+        [("HX-Retarget", "#upkeep-due"), ("HX-Reswap", "outerHTML")],
+        columns_template(&authorized_session, &pool, today, is_paused).await?,
+    )
+        .into_response())
+}
+
+/// After a change that stays within the card of the item, htmx requests only get that card back.
+async fn updated_card(
+    authorized_session: AuthorizedSession,
+    pool: Pool<Postgres>,
+    HxRequest(is_htmx): HxRequest,
+    id: i32,
+) -> AppResult<Response> {
+    if !is_htmx {
+        return Ok(
+            index_template(authorized_session, pool, FormState::default())
+                .await?
+                .into_response(),
+        );
+    }
+    Ok(card_template(&authorized_session, &pool, id)
+        .await?
+        .into_response())
+}
+
+/// Shows the card again with the errors of a form that was sent from it.
+async fn invalid_card(
+    authorized_session: AuthorizedSession,
+    pool: Pool<Postgres>,
+    HxRequest(is_htmx): HxRequest,
+    id: i32,
+    form: FormState,
+) -> AppResult<Response> {
+    if !is_htmx {
+        let mut index = index_template(authorized_session, pool, FormState::default()).await?;
+        if let Some(item) = index
+            .due_items
+            .iter_mut()
+            .chain(index.waiting.iter_mut())
+            .chain(index.backlog.iter_mut())
+            .find(|item| item.id == id)
+        {
+            item.form = form;
+        }
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, index).into_response());
+    }
+    let mut card = card_template(&authorized_session, &pool, id).await?;
+    card.item.form = form;
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, card).into_response())
+}
+
+async fn card_template(
+    authorized_session: &AuthorizedSession,
+    pool: &Pool<Postgres>,
+    id: i32,
+) -> AppResult<ItemCardPartTemplate> {
+    let account_id = authorized_session.account_id;
+    let today = Local::now().date_naive();
+    let item = fetch_upkeep_item(pool, id, account_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("This item does not exist"))?;
+    let is_due = is_due(
+        &item,
+        today,
+        is_paused(pool, account_id, today).await?,
+        account_id,
+    );
+    let completions: Box<[_]> =
+        fetch_upkeep_item_completions(pool, id, account_id, SUGGESTION_COMPLETIONS as i64)
+            .await?
+            .iter()
+            .map(|completion| completion.completed_at)
+            .collect();
+    let steps = fetch_upkeep_item_steps(pool, id, account_id).await?;
+    let (households, names) = fetch_part_households(pool, account_id).await?;
+
+    let part = PartItem {
+        suggested_cooldown: suggest_cooldown(item.cooldown_days, &completions),
+        sharing: sharing(&item, &households, &names),
+        ..part_item(&item, today, is_due)
+    };
+    Ok(ItemCardPartTemplate {
+        item: with_steps(part, part_steps(&steps.iter().collect::<Vec<_>>())),
+        households,
+    })
+}
+
+/// Items show up in the due column when they're due and it's up to the account to do them.
+fn is_due(item: &FetchUpkeepItem, today: NaiveDate, is_paused: bool, account_id: i32) -> bool {
+    !is_paused
+        && item.paused_since.is_none()
+        && item.due <= today
+        && item.is_assigned_to(account_id)
+}
+
+/// The due, waiting and backlog items, the part of the page that changes with the items.
+async fn columns_template(
+    authorized_session: &AuthorizedSession,
+    pool: &Pool<Postgres>,
+    today: NaiveDate,
+    is_paused: bool,
+) -> AppResult<ColumnsPartTemplate> {
+    let account_id = authorized_session.account_id;
+    let items = fetch_upkeep_items(pool, account_id).await?;
+    let capacity = fetch_upkeep_capacity(pool, account_id).await?;
+    let done = fetch_upkeep_done_since(pool, account_id, &today.and_time(NaiveTime::MIN)).await?;
+
+    let mut completions: HashMap<i32, Vec<NaiveDateTime>> = HashMap::new();
+    for completion in fetch_upkeep_completions(pool, account_id, SUGGESTION_COMPLETIONS as i64)
+        .await?
+        .iter()
+    {
+        completions
+            .entry(completion.upkeep_item_id)
+            .or_default()
+            .push(completion.completed_at);
+    }
+    let steps = fetch_steps_by_item(pool, account_id).await?;
+    let (households, names) = fetch_part_households(pool, account_id).await?;
+    let to_part_item = |item: &FetchUpkeepItem, is_due| {
+        let suggested_cooldown = completions
+            .get(&item.id)
+            .and_then(|completions| suggest_cooldown(item.cooldown_days, completions));
+        with_steps(
             PartItem {
-                id: item.id,
-                description: item.description.clone(),
-                due,
-                cooldown: format!("Cooldown: {} days", item.cooldown_days).into(),
-                render_complete: is_due,
-            }
-        })
-        .collect();
+                suggested_cooldown,
+                sharing: sharing(item, &households, &names),
+                ..part_item(item, today, is_due)
+            },
+            steps.get(&item.id).cloned().unwrap_or_default(),
+        )
+    };
+
+    let (mut due_items, backlog): (Vec<_>, Vec<_>) = items
+        .iter()
+        .partition(|item| is_due(item, today, is_paused, account_id));
+    // Anything that can't wait any longer goes first, items still inside their window after.
+    due_items.sort_by_key(|item| (item.latest_due() > today, item.latest_due()));
+    let (due_items, waiting) = fit_capacity(&due_items, &capacity, &done);
+
+    Ok(ColumnsPartTemplate {
+        due_items: due_items
+            .iter()
+            .map(|item| to_part_item(item, true))
+            .collect(),
+        waiting: waiting
+            .iter()
+            .map(|item| to_part_item(item, true))
+            .collect(),
+        backlog: backlog
+            .iter()
+            .map(|item| to_part_item(item, false))
+            .collect(),
+        households,
+        oob: true,
+    })
+}
+
+/// The create form is filled in with `form`, which is empty unless a submission was invalid.
+async fn index_template(
+    authorized_session: AuthorizedSession,
+    pool: Pool<Postgres>,
+    form: FormState,
+) -> AppResult<IndexTemplate> {
+    let today = Local::now().date_naive();
+    let pause = fetch_upkeep_pause(&pool, authorized_session.account_id, &today).await?;
+    let is_paused = matches!(&pause, Some(pause) if pause.pause_start <= today);
+    let columns = columns_template(&authorized_session, &pool, today, is_paused).await?;
+
+    let pause_notice = pause.map(|pause| match is_paused {
+        true => format!("Upkeep is paused until {}", pause.pause_end).into(),
+        false => format!(
+            "Upkeep will be paused from {} until {}",
+            pause.pause_start, pause.pause_end
+        )
+        .into(),
+    });
 
-    let (due_items, backlog) = items.split_at(split_at);
-    let due_items: Box<[_]> = due_items.into();
-    let backlog: Box<[_]> = backlog.into();
     Ok(IndexTemplate {
-        due_items,
-        backlog,
+        due_items: columns.due_items,
+        waiting: columns.waiting,
+        backlog: columns.backlog,
+        pause_notice,
+        households: columns.households,
+        form,
         session: authorized_session.clone().into(),
         authorized_session,
     })
 }
 
+fn parse_optional(value: &str) -> AppResult<Option<i32>> {
+    Ok(match value.trim() {
+        "" => None,
+        value => Some(value.parse()?),
+    })
+}
+
 #[derive(Deserialize, Clone)]
 pub struct PostIndexForm {
     title: Arc<str>,
     cooldown: Arc<str>,
+    effort: Arc<str>,
+    window: Arc<str>,
+    household: Arc<str>,
+}
+
+impl Validate for PostIndexForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([
+            Field::new("title", &self.title).required().max_length(255),
+            Field::new("cooldown", &self.cooldown)
+                .required()
+                .range(1, 3650),
+            Field::new("effort", &self.effort).range(1, 1440),
+            Field::new("window", &self.window).range(0, 3650),
+            Field::new("household", &self.household).range(1, i32::MAX),
+        ])
+    }
+}
+
+fn create_form_fragment(index: IndexTemplate) -> CreateFormPartTemplate {
+    CreateFormPartTemplate {
+        households: index.households,
+        form: index.form,
+        authorized_session: index.authorized_session,
+    }
 }
 
 pub async fn post_index(
-    session: Extension<AuthorizedSession>,
-    pool: Extension<Pool<Postgres>>,
-    CsrfForm(PostIndexForm { title, cooldown }): CsrfForm<PostIndexForm>,
-) -> AppResult<impl IntoResponse> {
-    let cooldown: i32 = cooldown.parse()?;
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    HxRequest(is_htmx): HxRequest,
+    ValidForm(form): ValidForm<PostIndexForm>,
+) -> AppResult<Response> {
+    let PostIndexForm {
+        title,
+        cooldown,
+        effort,
+        window,
+        household,
+    } = match form {
+        Ok(form) => form,
+        Err(form) => {
+            let template = index_template(authorized_session, pool, form).await?;
+            if !is_htmx {
+                return Ok((StatusCode::UNPROCESSABLE_ENTITY, template).into_response());
+            }
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                create_form_fragment(template),
+            )
+                .into_response());
+        }
+    };
+
+    let cooldown: i32 = cooldown.trim().parse()?;
+    let effort = parse_optional(&effort)?;
+    let window = parse_optional(&window)?.unwrap_or(0);
     let due = Local::now().date_naive() + Duration::days(cooldown as i64);
-    insert_upkeep_item(
-        &pool.0,
-        session.0.account_id,
-        title.as_ref(),
+    let inserted = insert_upkeep_item(
+        &pool,
+        authorized_session.account_id,
+        title.trim(),
         cooldown,
         &due,
+        effort,
+        window,
+        parse_optional(&household)?,
     )
     .await?;
-    get_index(session, pool).await
+    if !inserted {
+        return Err(AppError::not_found("This household does not exist"));
+    }
+    flash.success(format!("Created \"{}\"", title.trim()));
+    let template = index_template(authorized_session, pool, FormState::default()).await?;
+    if !is_htmx {
+        return Ok(template.into_response());
+    }
+    // The columns fetch themselves when they hear about the new item.
+    Ok((
+        [("HX-Trigger", "upkeep-created")],
+        create_form_fragment(template),
+    )
+        .into_response())
 }
 
 pub async fn post_complete(
     session: Extension<AuthorizedSession>,
     pool: Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    hx: HxRequest,
     Path(id): Path<i32>,
 ) -> AppResult<impl IntoResponse> {
     complete_upkeep_item(&pool.0, id, session.0.account_id).await?;
-    get_index(session, pool).await
+    flash.success("Done, it will be back when it is due again");
+    updated_columns(session.0, pool.0, hx).await
 }
 
 pub async fn delete_item(
     session: Extension<AuthorizedSession>,
     pool: Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    hx: HxRequest,
     Path(id): Path<i32>,
 ) -> AppResult<impl IntoResponse> {
     delete_upkeep_item(&pool.0, id, session.0.account_id).await?;
-    get_index(session, pool).await
+    flash.success("The item has been deleted");
+    updated_columns(session.0, pool.0, hx).await
 }
 
 #[derive(Deserialize)]
@@ -128,9 +594,1023 @@ pub struct PatchItemForm {
 pub async fn patch_item(
     session: Extension<AuthorizedSession>,
     pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
     Path(id): Path<i32>,
     Form(PatchItemForm { due_date }): Form<PatchItemForm>,
 ) -> AppResult<impl IntoResponse> {
     patch_due_date_upkeep_item(&pool.0, id, session.0.account_id, &due_date).await?;
-    get_index(session, pool).await
+    updated_columns(session.0, pool.0, hx).await
+}
+
+#[derive(Deserialize)]
+pub struct WindowForm {
+    window_start: NaiveDate,
+    window_end: NaiveDate,
+}
+
+pub async fn post_window(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    Form(WindowForm {
+        window_start,
+        window_end,
+    }): Form<WindowForm>,
+) -> AppResult<impl IntoResponse> {
+    // The same limit as on create, so the number of days always fits.
+    let window_days = match (window_end - window_start).num_days() {
+        days if days < 0 => {
+            return Err(AppError::validation("A window cannot end before it starts"))
+        }
+        days if days > 3650 => {
+            return Err(AppError::validation(
+                "A window can be at most 3650 days long",
+            ))
+        }
+        days => days as i32,
+    };
+    patch_window_upkeep_item(
+        &pool.0,
+        id,
+        session.0.account_id,
+        &window_start,
+        window_days,
+    )
+    .await?;
+    updated_columns(session.0, pool.0, hx).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CooldownForm {
+    cooldown_days: Arc<str>,
+}
+
+impl Validate for CooldownForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([Field::new("cooldown_days", &self.cooldown_days)
+            .required()
+            .range(1, 3650)])
+    }
+}
+
+pub async fn post_cooldown(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    ValidForm(form): ValidForm<CooldownForm>,
+) -> AppResult<Response> {
+    let cooldown_days: i32 = match form {
+        Ok(form) => form.cooldown_days.trim().parse()?,
+        Err(form) => return invalid_card(session.0, pool.0, hx, id, form).await,
+    };
+
+    patch_cooldown_upkeep_item(&pool.0, id, session.0.account_id, cooldown_days).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EffortForm {
+    effort: Arc<str>,
+}
+
+impl Validate for EffortForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([Field::new("effort", &self.effort).range(1, 1440)])
+    }
+}
+
+/// An empty effort removes it from the item.
+pub async fn post_effort(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    ValidForm(form): ValidForm<EffortForm>,
+) -> AppResult<Response> {
+    let effort = match form {
+        Ok(form) => parse_optional(&form.effort)?,
+        Err(form) => return invalid_card(session.0, pool.0, hx, id, form).await,
+    };
+    patch_effort_upkeep_item(&pool.0, id, session.0.account_id, effort).await?;
+    updated_columns(session.0, pool.0, hx).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct StepForm {
+    step: Arc<str>,
+}
+
+impl Validate for StepForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([Field::new("step", &self.step).required().max_length(255)])
+    }
+}
+
+pub async fn post_step(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    ValidForm(form): ValidForm<StepForm>,
+) -> AppResult<Response> {
+    let StepForm { step } = match form {
+        Ok(form) => form,
+        Err(form) => return invalid_card(session.0, pool.0, hx, id, form).await,
+    };
+
+    insert_upkeep_step(&pool.0, id, session.0.account_id, step.trim()).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+pub async fn post_toggle_step(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path((id, step_id)): Path<(i32, i32)>,
+) -> AppResult<impl IntoResponse> {
+    toggle_upkeep_step(&pool.0, step_id, id, session.0.account_id).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+pub async fn post_move_up_step(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path((id, step_id)): Path<(i32, i32)>,
+) -> AppResult<impl IntoResponse> {
+    move_up_upkeep_step(&pool.0, step_id, id, session.0.account_id).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+pub async fn delete_step(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path((id, step_id)): Path<(i32, i32)>,
+) -> AppResult<impl IntoResponse> {
+    delete_upkeep_step(&pool.0, step_id, id, session.0.account_id).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+pub async fn post_focus_toggle_step(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Path((id, step_id)): Path<(i32, i32)>,
+    query: Query<FocusQuery>,
+) -> AppResult<impl IntoResponse> {
+    toggle_upkeep_step(&pool.0, step_id, id, session.0.account_id).await?;
+    get_focus(session, pool, query).await
+}
+
+#[derive(Deserialize)]
+pub struct HouseholdForm {
+    household: Arc<str>,
+    assignment_mode: AssignmentMode,
+}
+
+pub async fn post_item_household(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    Form(HouseholdForm {
+        household,
+        assignment_mode,
+    }): Form<HouseholdForm>,
+) -> AppResult<impl IntoResponse> {
+    let shared = share_upkeep_item(
+        &pool.0,
+        id,
+        session.0.account_id,
+        parse_optional(&household)?,
+        assignment_mode,
+    )
+    .await?;
+    if !shared {
+        return Err(AppError::not_found("This item or household does not exist"));
+    }
+    updated_columns(session.0, pool.0, hx).await
+}
+
+pub async fn post_pause_item(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    pause_upkeep_item(&pool.0, id, session.0.account_id).await?;
+    flash.success("The item is paused until you resume it");
+    updated_columns(session.0, pool.0, hx).await
+}
+
+pub async fn post_resume_item(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    resume_upkeep_item(&pool.0, id, session.0.account_id).await?;
+    flash.success("The item has been resumed");
+    updated_columns(session.0, pool.0, hx).await
+}
+
+#[derive(Deserialize)]
+pub struct ResumePolicyForm {
+    resume_policy: ResumePolicy,
+}
+
+pub async fn post_resume_policy(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    hx: HxRequest,
+    Path(id): Path<i32>,
+    Form(ResumePolicyForm { resume_policy }): Form<ResumePolicyForm>,
+) -> AppResult<impl IntoResponse> {
+    patch_resume_policy_upkeep_item(&pool.0, id, session.0.account_id, resume_policy).await?;
+    updated_card(session.0, pool.0, hx, id).await
+}
+
+pub async fn get_settings(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<impl IntoResponse> {
+    let today = Local::now().date_naive();
+    let pause = fetch_upkeep_pause(&pool, authorized_session.account_id, &today).await?;
+    let is_paused = matches!(&pause, Some(pause) if pause.pause_start <= today);
+    let capacity = fetch_upkeep_capacity(&pool, authorized_session.account_id).await?;
+    let feed_token = fetch_upkeep_feed_token(&pool, authorized_session.account_id).await?;
+
+    Ok(SettingsTemplate {
+        daily_item_limit: capacity.daily_item_limit,
+        daily_minute_limit: capacity.daily_minute_limit,
+        pause_start: pause.as_ref().map(|pause| pause.pause_start),
+        pause_end: pause.as_ref().map(|pause| pause.pause_end),
+        is_paused,
+        feed_token,
+        session: authorized_session.clone().into(),
+        authorized_session,
+    })
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostCapacityForm {
+    daily_item_limit: Arc<str>,
+    daily_minute_limit: Arc<str>,
+}
+
+pub async fn post_capacity(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Form(PostCapacityForm {
+        daily_item_limit,
+        daily_minute_limit,
+    }): Form<PostCapacityForm>,
+) -> AppResult<impl IntoResponse> {
+    upsert_upkeep_capacity(
+        &pool.0,
+        session.0.account_id,
+        parse_optional(&daily_item_limit)?,
+        parse_optional(&daily_minute_limit)?,
+    )
+    .await?;
+    get_settings(session, pool).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostPauseForm {
+    pause_start: NaiveDate,
+    pause_end: NaiveDate,
+}
+
+pub async fn post_pause(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Form(PostPauseForm {
+        pause_start,
+        pause_end,
+    }): Form<PostPauseForm>,
+) -> AppResult<impl IntoResponse> {
+    if pause_end < pause_start {
+        return Err(AppError::validation("A pause cannot end before it starts"));
+    };
+
+    if pause_end < Local::now().date_naive() {
+        return Err(AppError::validation("A pause cannot end in the past"));
+    };
+
+    upsert_upkeep_pause(&pool.0, session.0.account_id, &pause_start, &pause_end).await?;
+    get_settings(session, pool).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostResumeForm {}
+
+pub async fn post_resume(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Form(PostResumeForm {}): Form<PostResumeForm>,
+) -> AppResult<impl IntoResponse> {
+    let today = Local::now().date_naive();
+    let account_id = session.0.account_id;
+
+    let mut transaction = pool.0.begin().await?;
+    if let Some(pause) = take_upkeep_pause(&mut *transaction, account_id).await? {
+        if pause.pause_start <= today {
+            resume_upkeep_items_after_pause(
+                &mut *transaction,
+                account_id,
+                &pause.pause_start,
+                &pause.resumed_on(today),
+            )
+            .await?;
+        }
+    };
+    transaction.commit().await?;
+
+    get_settings(session, pool).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostFeedForm {}
+
+/// Creates the feed token, or replaces it so the previous feed URL stops working.
+pub async fn post_feed(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Form(PostFeedForm {}): Form<PostFeedForm>,
+) -> AppResult<impl IntoResponse> {
+    let mut feed_token_bytes = [0u8; 33];
+    OsRng.fill_bytes(&mut feed_token_bytes);
+    let feed_token = URL_SAFE.encode(feed_token_bytes);
+
+    upsert_upkeep_feed_token(&pool.0, session.0.account_id, &feed_token).await?;
+    get_settings(session, pool).await
+}
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    component: Option<Component>,
+}
+
+pub async fn get_feed(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Path(feed_token): Path<Arc<str>>,
+    Query(FeedQuery { component }): Query<FeedQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let account_id = match fetch_upkeep_feed_account(&pool, &feed_token).await? {
+        Some(account_id) => account_id,
+        None => return Err(AppError::not_found("This feed does not exist")),
+    };
+
+    let items = fetch_assigned_items(&pool, account_id).await?;
+    let calendar = render_calendar(&items, component.unwrap_or_default());
+    let etag = etag(&calendar);
+
+    if is_unchanged(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+
+    Ok((
+        [
+            (CONTENT_TYPE, "text/calendar; charset=utf-8".into()),
+            (CACHE_CONTROL, "no-cache".into()),
+            (ETAG, etag),
+        ],
+        calendar,
+    )
+        .into_response())
+}
+
+async fn apply_changes(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+    changes: &[(i32, NaiveDate)],
+) -> AppResult<()> {
+    let mut transaction = pool.begin().await?;
+    for (id, due) in changes.iter() {
+        patch_due_date_upkeep_item(&mut *transaction, *id, account_id, due).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CatchUpQuery {
+    days: Option<usize>,
+    daily_limit: Option<usize>,
+}
+
+/// Plans the catch-up for the given number of days, falling back to the daily item limit from
+/// the capacity settings. Nothing is planned while upkeep is paused.
+async fn catch_up_plan(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+    CatchUpQuery { days, daily_limit }: CatchUpQuery,
+) -> AppResult<(usize, usize, bool, Box<[PlannedChange]>)> {
+    let capacity = fetch_upkeep_capacity(pool, account_id).await?;
+    let days = days.unwrap_or(7).clamp(1, 60);
+    let daily_limit = daily_limit
+        .or(capacity.daily_item_limit.map(|limit| limit as usize))
+        .unwrap_or(3)
+        .max(1);
+
+    let today = Local::now().date_naive();
+    let is_paused = is_paused(pool, account_id, today).await?;
+    let changes = match is_paused {
+        true => Box::from([]),
+        false => {
+            let items = fetch_assigned_items(pool, account_id).await?;
+            plan_catch_up(&items, today, days, daily_limit)
+        }
+    };
+    Ok((days, daily_limit, is_paused, changes))
+}
+
+pub async fn get_catch_up(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Query(query): Query<CatchUpQuery>,
+) -> AppResult<impl IntoResponse> {
+    let (days, daily_limit, is_paused, changes) =
+        catch_up_plan(&pool, authorized_session.account_id, query).await?;
+
+    Ok(CatchUpTemplate {
+        days,
+        daily_limit,
+        is_paused,
+        encoded_changes: encode_changes(&changes),
+        changes,
+        session: authorized_session.clone().into(),
+        authorized_session,
+    })
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostCatchUpForm {
+    changes: Arc<str>,
+    days: Option<usize>,
+    daily_limit: Option<usize>,
+}
+
+pub async fn post_catch_up(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    Form(PostCatchUpForm {
+        changes,
+        days,
+        daily_limit,
+    }): Form<PostCatchUpForm>,
+) -> AppResult<impl IntoResponse> {
+    let query = CatchUpQuery { days, daily_limit };
+    let (_, _, is_paused, planned) =
+        catch_up_plan(&pool, authorized_session.account_id, query).await?;
+    if is_paused {
+        return Err(AppError::validation(
+            "Upkeep is paused, resume it before catching up",
+        ));
+    }
+
+    // The plan is made again rather than trusted, the accepted changes have to be exactly the ones
+    // the server would plan now.
+    let changes = decode_changes(&changes)?;
+    let is_planned = changes.len() == planned.len()
+        && changes
+            .iter()
+            .zip(planned.iter())
+            .all(|((id, due), change)| *id == change.id && *due == change.to);
+    if !is_planned {
+        return Err(AppError::conflict(
+            "Your upkeep changed since the plan was made, preview it again",
+        ));
+    }
+
+    apply_changes(&pool, authorized_session.account_id, &changes).await?;
+    flash.success("Your upkeep has been rescheduled");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("HX-Location", "/core/upkeep".parse()?);
+    Ok(headers)
+}
+
+#[derive(Deserialize)]
+pub struct FocusQuery {
+    skip: Option<usize>,
+}
+
+pub async fn get_focus(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Query(FocusQuery { skip }): Query<FocusQuery>,
+) -> AppResult<impl IntoResponse> {
+    let today = Local::now().date_naive();
+    let is_paused = is_paused(&pool, authorized_session.account_id, today).await?;
+
+    let items = fetch_assigned_items(&pool, authorized_session.account_id).await?;
+    let candidates = match is_paused {
+        true => Box::from([]),
+        false => focus_candidates(&items, today),
+    };
+
+    // Skipping past the last candidate starts over, "not this one" should never be a dead end.
+    let skip = match candidates.len() {
+        0 => 0,
+        len => skip.unwrap_or(0) % len,
+    };
+
+    let mut steps = fetch_steps_by_item(&pool, authorized_session.account_id).await?;
+    Ok(FocusTemplate {
+        item: candidates.get(skip).map(|item| {
+            with_steps(
+                part_item(item, today, true),
+                steps.remove(&item.id).unwrap_or_default(),
+            )
+        }),
+        skip,
+        next_skip: skip + 1,
+        is_paused,
+        session: authorized_session.into(),
+    })
+}
+
+pub async fn post_focus_complete(
+    session: Extension<AuthorizedSession>,
+    pool: Extension<Pool<Postgres>>,
+    Path(id): Path<i32>,
+    query: Query<FocusQuery>,
+) -> AppResult<impl IntoResponse> {
+    complete_upkeep_item(&pool.0, id, session.0.account_id).await?;
+    get_focus(session, pool, query).await
+}
+
+#[derive(Deserialize)]
+pub struct ForecastQuery {
+    weeks: Option<usize>,
+    tolerance: Option<i64>,
+}
+
+async fn forecast_template(
+    authorized_session: AuthorizedSession,
+    pool: &Pool<Postgres>,
+    ForecastQuery { weeks, tolerance }: ForecastQuery,
+    level: bool,
+) -> AppResult<ForecastTemplate> {
+    let weeks = weeks.unwrap_or(4).clamp(4, 8);
+    let tolerance = tolerance.unwrap_or(2).clamp(1, 7);
+
+    let today = Local::now().date_naive();
+    let items = fetch_assigned_items(pool, authorized_session.account_id).await?;
+    let changes = match level {
+        true => level_schedule(&items, today, weeks * 7, tolerance),
+        false => Box::from([]),
+    };
+
+    Ok(ForecastTemplate {
+        weeks,
+        tolerance,
+        days: forecast(&items, today, weeks * 7, &changes),
+        level,
+        encoded_changes: encode_changes(&changes),
+        changes,
+        session: authorized_session.clone().into(),
+        authorized_session,
+    })
+}
+
+pub async fn get_forecast(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Query(query): Query<ForecastQuery>,
+) -> AppResult<impl IntoResponse> {
+    forecast_template(authorized_session, &pool, query, false).await
+}
+
+pub async fn get_level(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Query(query): Query<ForecastQuery>,
+) -> AppResult<impl IntoResponse> {
+    forecast_template(authorized_session, &pool, query, true).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostLevelForm {
+    changes: Arc<str>,
+    weeks: Arc<str>,
+    tolerance: Arc<str>,
+}
+
+pub async fn post_level(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Form(PostLevelForm {
+        changes,
+        weeks,
+        tolerance,
+    }): Form<PostLevelForm>,
+) -> AppResult<impl IntoResponse> {
+    let changes = decode_changes(&changes)?;
+    apply_changes(&pool, authorized_session.account_id, &changes).await?;
+
+    let query = ForecastQuery {
+        weeks: Some(weeks.parse()?),
+        tolerance: Some(tolerance.parse()?),
+    };
+    forecast_template(authorized_session, &pool, query, false).await
+}
+
+async fn households_template(
+    authorized_session: AuthorizedSession,
+    pool: &Pool<Postgres>,
+) -> AppResult<HouseholdsTemplate> {
+    let (households, _) = fetch_part_households(pool, authorized_session.account_id).await?;
+    let invites = fetch_household_invites(pool, authorized_session.account_id)
+        .await?
+        .iter()
+        .map(|invite| PartHouseholdInvite {
+            household_id: invite.household_id,
+            household: invite.household.clone(),
+            invited_by: invite.invited_by.clone(),
+        })
+        .collect();
+    let history = fetch_household_history(pool, authorized_session.account_id)
+        .await?
+        .iter()
+        .map(|completion| PartHouseholdCompletion {
+            household: completion.household.clone(),
+            description: completion.description.clone(),
+            completed_by: completion.completed_by.clone(),
+            completed_at: completion
+                .completed_at
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .into(),
+        })
+        .collect();
+
+    Ok(HouseholdsTemplate {
+        households,
+        invites,
+        history,
+        form: FormState::default(),
+        invite_household: None,
+        invite_form: FormState::default(),
+        session: authorized_session.clone().into(),
+        authorized_session,
+    })
+}
+
+pub async fn get_households(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<impl IntoResponse> {
+    households_template(authorized_session, &pool).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostHouseholdForm {
+    name: Arc<str>,
+}
+
+impl Validate for PostHouseholdForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([Field::new("name", &self.name).required().max_length(255)])
+    }
+}
+
+pub async fn post_household(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    ValidForm(form): ValidForm<PostHouseholdForm>,
+) -> AppResult<Response> {
+    let PostHouseholdForm { name } = match form {
+        Ok(form) => form,
+        Err(form) => {
+            let template = HouseholdsTemplate {
+                form,
+                ..households_template(authorized_session, &pool).await?
+            };
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, template).into_response());
+        }
+    };
+
+    insert_household(&pool, authorized_session.account_id, name.trim()).await?;
+    Ok(households_template(authorized_session, &pool)
+        .await?
+        .into_response())
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostHouseholdMemberForm {
+    email: Arc<str>,
+}
+
+impl Validate for PostHouseholdMemberForm {
+    fn fields(&self) -> Box<[Field<'_>]> {
+        Box::from([Field::new("email", &self.email)
+            .required()
+            .max_length(255)
+            .email()])
+    }
+}
+
+pub async fn post_household_member(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    Path(id): Path<i32>,
+    ValidForm(form): ValidForm<PostHouseholdMemberForm>,
+) -> AppResult<Response> {
+    if !is_household_member(&pool, id, authorized_session.account_id).await? {
+        return Err(AppError::not_found("This household does not exist"));
+    }
+    let PostHouseholdMemberForm { email } = match form {
+        Ok(form) => form,
+        Err(form) => {
+            let template = HouseholdsTemplate {
+                invite_household: Some(id),
+                invite_form: form,
+                ..households_template(authorized_session, &pool).await?
+            };
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, template).into_response());
+        }
+    };
+    // The same answer either way, whether an account uses the email is nobody else's business.
+    insert_household_invite(&pool, id, authorized_session.account_id, email.trim()).await?;
+    flash.success(format!(
+        "If {} belongs to an account, it is invited to join",
+        email.trim()
+    ));
+    Ok(households_template(authorized_session, &pool)
+        .await?
+        .into_response())
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostHouseholdInviteForm {}
+
+pub async fn post_accept_household_invite(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    Path(id): Path<i32>,
+    Form(PostHouseholdInviteForm {}): Form<PostHouseholdInviteForm>,
+) -> AppResult<impl IntoResponse> {
+    if !accept_household_invite(&pool, id, authorized_session.account_id).await? {
+        return Err(AppError::not_found("This invite does not exist"));
+    }
+    flash.success("You joined the household");
+    households_template(authorized_session, &pool).await
+}
+
+pub async fn post_decline_household_invite(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Path(id): Path<i32>,
+    Form(PostHouseholdInviteForm {}): Form<PostHouseholdInviteForm>,
+) -> AppResult<impl IntoResponse> {
+    delete_household_invite(&pool, id, authorized_session.account_id).await?;
+    households_template(authorized_session, &pool).await
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostLeaveHouseholdForm {}
+
+pub async fn post_leave_household(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Path(id): Path<i32>,
+    Form(PostLeaveHouseholdForm {}): Form<PostLeaveHouseholdForm>,
+) -> AppResult<impl IntoResponse> {
+    leave_household(&pool, id, authorized_session.account_id).await?;
+    households_template(authorized_session, &pool).await
+}
+
+pub async fn get_export_csv(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<impl IntoResponse> {
+    let items = fetch_upkeep_items(&pool, authorized_session.account_id).await?;
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (CONTENT_DISPOSITION, r#"attachment; filename="upkeep.csv""#),
+        ],
+        export_csv(&items)?,
+    ))
+}
+
+pub async fn get_export_json(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<impl IntoResponse> {
+    let items = fetch_upkeep_items(&pool, authorized_session.account_id).await?;
+    Ok((
+        [
+            (CONTENT_TYPE, "application/json"),
+            (CONTENT_DISPOSITION, r#"attachment; filename="upkeep.json""#),
+        ],
+        export_json(&items)?,
+    ))
+}
+
+async fn check_import(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+    rows: &[ParsedRow],
+) -> AppResult<Box<[RowStatus]>> {
+    let existing = fetch_upkeep_items(pool, account_id).await?;
+    let memberships: Box<[Membership]> = fetch_household_members(pool, account_id)
+        .await?
+        .iter()
+        .map(|member| Membership {
+            household_id: member.household_id,
+            account_id: member.account_id,
+        })
+        .collect();
+    Ok(check_rows(
+        rows,
+        &existing,
+        account_id,
+        &memberships,
+        Local::now().date_naive(),
+    ))
+}
+
+fn import_template(authorized_session: AuthorizedSession) -> ImportTemplate {
+    ImportTemplate {
+        rows: Box::from([]),
+        new_count: 0,
+        encoded_items: "".into(),
+        encoded_skipped: "".into(),
+        notice: None,
+        summary: None,
+        session: authorized_session.clone().into(),
+        authorized_session,
+    }
+}
+
+pub async fn get_import(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+) -> AppResult<impl IntoResponse> {
+    Ok(import_template(authorized_session))
+}
+
+fn skipped_reason(status: &RowStatus) -> Option<Box<str>> {
+    match status {
+        RowStatus::New(_) => None,
+        RowStatus::Duplicate => Some("Already exists".into()),
+        RowStatus::Invalid(errors) => Some(errors.join(", ").into()),
+    }
+}
+
+/// Only previews the upload, nothing is stored until the preview is confirmed. Being multipart,
+/// the upload carries its CSRF token in the header htmx adds to every request.
+pub async fn post_import(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    mut multipart: Multipart,
+) -> AppResult<Response> {
+    let mut source = None;
+    let mut data = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("source") => source = Some(field.text().await?),
+            Some("file") => data = Some(field.text().await?),
+            _ => {}
+        }
+    }
+
+    let source = source
+        .as_deref()
+        .map_or(Some(Source::Reduce), Source::from_name)
+        .ok_or_else(|| AppError::validation("Unknown import source"))?;
+    let upload = match parse_upload(source, data.as_deref().unwrap_or_default()) {
+        Ok(upload) => upload,
+        Err(error) => {
+            let notice = format!("The file could not be read: {}", error).into();
+            return Ok(ImportTemplate {
+                notice: Some(notice),
+                ..import_template(authorized_session)
+            }
+            .into_response());
+        }
+    };
+    let statuses = check_import(&pool, authorized_session.account_id, &upload.rows).await?;
+
+    let mut to_import = Vec::new();
+    let mut skipped = Vec::new();
+    let mut preview: Vec<PartImportRow> = upload
+        .rows
+        .iter()
+        .zip(statuses.iter())
+        .map(|(row, status)| {
+            let description: Box<str> = row
+                .item
+                .as_ref()
+                .map(|item| item.description.as_str())
+                .unwrap_or_default()
+                .into();
+            if let (RowStatus::New(_), Ok(item)) = (status, &row.item) {
+                to_import.push(item.clone());
+            }
+            if let Some(reason) = skipped_reason(status) {
+                skipped.push(PartSkippedItem {
+                    description: match description.as_ref() {
+                        "" => format!("Line {}", row.line).into(),
+                        _ => description.clone(),
+                    },
+                    reason,
+                });
+            }
+            PartImportRow {
+                line: row.line,
+                description,
+                is_new: matches!(status, RowStatus::New(_)),
+                is_duplicate: matches!(status, RowStatus::Duplicate),
+                errors: match status {
+                    RowStatus::Invalid(errors) => errors.clone(),
+                    _ => Box::from([]),
+                },
+                skipped: None,
+            }
+        })
+        .collect();
+    for task in upload.skipped.iter() {
+        skipped.push(PartSkippedItem {
+            description: task.description.as_str().into(),
+            reason: task.reason.as_str().into(),
+        });
+        preview.push(PartImportRow {
+            line: task.line,
+            description: task.description.as_str().into(),
+            is_new: false,
+            is_duplicate: false,
+            errors: Box::from([]),
+            skipped: Some(task.reason.as_str().into()),
+        });
+    }
+    preview.sort_by_key(|row| row.line);
+
+    Ok(ImportTemplate {
+        rows: preview.into(),
+        new_count: to_import.len(),
+        encoded_items: serde_json::to_string(&to_import)?.into(),
+        encoded_skipped: serde_json::to_string(&skipped)?.into(),
+        ..import_template(authorized_session)
+    }
+    .into_response())
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostImportConfirmForm {
+    items: Arc<str>,
+    skipped: Arc<str>,
+}
+
+pub async fn post_import_confirm(
+    Extension(authorized_session): Extension<AuthorizedSession>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(flash): Extension<Flash>,
+    Form(PostImportConfirmForm { items, skipped }): Form<PostImportConfirmForm>,
+) -> AppResult<impl IntoResponse> {
+    // Checked again, the list may have changed since the preview was made.
+    let upload = parse_upload(Source::Reduce, &items)?;
+    let statuses = check_import(&pool, authorized_session.account_id, &upload.rows).await?;
+    let mut skipped: Vec<PartSkippedItem> = serde_json::from_str(&skipped)?;
+
+    let mut transaction = pool.begin().await?;
+    let mut imported = 0;
+    for (row, status) in upload.rows.iter().zip(statuses.iter()) {
+        if let RowStatus::New(item) = status {
+            import_upkeep_item(&mut *transaction, authorized_session.account_id, item).await?;
+            imported += 1;
+        } else if let (Some(reason), Ok(item)) = (skipped_reason(status), &row.item) {
+            skipped.push(PartSkippedItem {
+                description: item.description.as_str().into(),
+                reason,
+            });
+        }
+    }
+    transaction.commit().await?;
+
+    match skipped.len() {
+        0 => flash.success(format!("Imported {} items", imported)),
+        count => flash.warning(format!(
+            "Imported {} items, {} were left out",
+            imported, count
+        )),
+    }
+    Ok(ImportTemplate {
+        summary: Some(PartImportSummary {
+            imported,
+            skipped: skipped.into(),
+        }),
+        ..import_template(authorized_session)
+    })
 }
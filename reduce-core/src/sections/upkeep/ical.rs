@@ -0,0 +1,352 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{Duration, NaiveDate};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::database::FetchUpkeepItem;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Component {
+    #[default]
+    Event,
+    Todo,
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Folds a content line to at most 75 octets per line, as required by RFC 5545.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            calendar.push_str("\r\n ");
+            length = 1;
+        }
+        calendar.push(character);
+        length += character.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn push_item(calendar: &mut String, item: &FetchUpkeepItem, component: Component) {
+    let name = match component {
+        Component::Event => "VEVENT",
+        Component::Todo => "VTODO",
+    };
+    push_line(calendar, &format!("BEGIN:{}", name));
+    push_line(calendar, &format!("UID:{}", uid(item.id)));
+    push_line(calendar, &format!("SEQUENCE:{}", item.revision));
+    push_line(
+        calendar,
+        &format!("DTSTAMP:{}", item.updated_at.format("%Y%m%dT%H%M%SZ")),
+    );
+    push_line(
+        calendar,
+        &format!("SUMMARY:{}", escape_text(&item.description)),
+    );
+    let description = match item.effort_minutes {
+        Some(effort) => format!(
+            "DESCRIPTION:Every {} days\\, takes about {} minutes",
+            item.cooldown_days, effort
+        ),
+        None => format!("DESCRIPTION:Every {} days", item.cooldown_days),
+    };
+    push_line(calendar, &description);
+    match component {
+        Component::Event => {
+            push_line(
+                calendar,
+                &format!("DTSTART;VALUE=DATE:{}", format_date(item.due)),
+            );
+            push_line(
+                calendar,
+                &format!(
+                    "DTEND;VALUE=DATE:{}",
+                    format_date(item.latest_due() + Duration::days(1))
+                ),
+            );
+            push_line(calendar, "TRANSP:TRANSPARENT");
+        }
+        Component::Todo => {
+            if item.window_days > 0 {
+                push_line(
+                    calendar,
+                    &format!("DTSTART;VALUE=DATE:{}", format_date(item.due)),
+                );
+            }
+            push_line(
+                calendar,
+                &format!("DUE;VALUE=DATE:{}", format_date(item.latest_due())),
+            );
+            push_line(calendar, "STATUS:NEEDS-ACTION");
+        }
+    }
+    push_line(calendar, &format!("END:{}", name));
+}
+
+fn push_header(calendar: &mut String) {
+    push_line(calendar, "BEGIN:VCALENDAR");
+    push_line(calendar, "VERSION:2.0");
+    push_line(calendar, "PRODID:-//Reduce//Upkeep//EN");
+    push_line(calendar, "CALSCALE:GREGORIAN");
+}
+
+pub fn etag(calendar: &str) -> String {
+    let hash: String = Sha256::digest(calendar.as_bytes())[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("\"{}\"", hash)
+}
+
+pub fn uid(id: i32) -> String {
+    format!("upkeep-{}@reduce", id)
+}
+
+/// Renders the items as all-day entries. The output only depends on the items themselves, so an
+/// unchanged list always produces the same calendar and thus the same ETag.
+pub fn render_calendar(items: &[FetchUpkeepItem], component: Component) -> String {
+    let mut calendar = String::new();
+    push_header(&mut calendar);
+    push_line(&mut calendar, "X-WR-CALNAME:Upkeep");
+    for item in items.iter().filter(|item| item.paused_since.is_none()) {
+        push_item(&mut calendar, item, component);
+    }
+    push_line(&mut calendar, "END:VCALENDAR");
+    calendar
+}
+
+/// Renders a single item as a calendar object resource, as served over CalDAV.
+pub fn render_todo(item: &FetchUpkeepItem) -> String {
+    let mut calendar = String::new();
+    push_header(&mut calendar);
+    push_item(&mut calendar, item, Component::Todo);
+    push_line(&mut calendar, "END:VCALENDAR");
+    calendar
+}
+
+/// The parts of a VTODO sent by a client that map onto an upkeep item.
+pub struct ParsedTodo {
+    pub summary: Option<String>,
+    pub due: Option<NaiveDate>,
+    pub is_completed: bool,
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => match characters.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(character) => unescaped.push(character),
+                None => {}
+            },
+            character => unescaped.push(character),
+        }
+    }
+    unescaped
+}
+
+pub fn parse_todo(calendar: &str) -> Option<ParsedTodo> {
+    let unfolded = calendar
+        .replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut todo = None;
+    for line in unfolded.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, value),
+            None => continue,
+        };
+        let name = name.split(';').next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value.trim()) {
+            ("BEGIN", "VTODO") => {
+                todo = Some(ParsedTodo {
+                    summary: None,
+                    due: None,
+                    is_completed: false,
+                })
+            }
+            ("END", "VTODO") => break,
+            _ => {}
+        };
+        let todo = match todo.as_mut() {
+            Some(todo) => todo,
+            None => continue,
+        };
+        match (name.as_str(), value.trim()) {
+            ("SUMMARY", value) => todo.summary = Some(unescape_text(value)),
+            ("DUE", value) => {
+                todo.due = value
+                    .get(..8)
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+            }
+            ("STATUS", status) => todo.is_completed = status.eq_ignore_ascii_case("COMPLETED"),
+            ("COMPLETED", _) => todo.is_completed = true,
+            _ => {}
+        };
+    }
+    todo
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::sections::upkeep::database::{AssignmentMode, ResumePolicy};
+
+    fn item(description: &str) -> FetchUpkeepItem {
+        FetchUpkeepItem {
+            id: 7,
+            description: description.into(),
+            cooldown_days: 3,
+            due: NaiveDate::from_ymd_opt(2024, 6, 10).unwrap(),
+            paused_since: None,
+            resume_policy: ResumePolicy::Shift,
+            effort_minutes: None,
+            window_days: 2,
+            household_id: None,
+            assignment_mode: AssignmentMode::Rotate,
+            assigned_account_id: None,
+            revision: 4,
+            updated_at: NaiveDateTime::parse_from_str("2024-06-01 08:30:15", "%Y-%m-%d %H:%M:%S")
+                .unwrap(),
+        }
+    }
+
+    fn lines(calendar: &str) -> Vec<&str> {
+        calendar.split("\r\n").collect()
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape_text("a\\b;c,d\r\ne"), "a\\\\b\\;c\\,d\\ne");
+        assert_eq!(unescape_text(&escape_text("a\\b;c,d\ne")), "a\\b;c,d\ne");
+    }
+
+    #[test]
+    fn long_lines_are_folded_at_75_octets() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, &"a".repeat(160));
+
+        let lines = lines(&calendar);
+        assert_eq!(
+            lines.iter().map(|line| line.len()).collect::<Vec<_>>(),
+            [75, 75, 12, 0]
+        );
+        assert!(lines[1..3].iter().all(|line| line.starts_with(' ')));
+    }
+
+    #[test]
+    fn folding_never_splits_a_character() {
+        let mut calendar = String::new();
+        push_line(
+            &mut calendar,
+            &format!("{}{}", "a".repeat(74), "é".repeat(3)),
+        );
+
+        let lines = lines(&calendar);
+        assert_eq!(lines[0], "a".repeat(74));
+        assert_eq!(lines[1], " ééé");
+    }
+
+    #[test]
+    fn items_are_stamped_with_their_update_time() {
+        let calendar = render_todo(&item("Water plants"));
+        let lines = lines(&calendar);
+
+        assert!(lines.contains(&"DTSTAMP:20240601T083015Z"));
+        assert!(lines.contains(&"DUE;VALUE=DATE:20240612"));
+        assert!(lines.contains(&"UID:upkeep-7@reduce"));
+    }
+
+    #[test]
+    fn rendered_todos_parse_back() {
+        let description = format!("Clean, sort;{}", " and put away".repeat(10));
+        let todo = parse_todo(&render_todo(&item(&description))).unwrap();
+
+        assert_eq!(todo.summary.as_deref(), Some(description.as_str()));
+        assert_eq!(todo.due, NaiveDate::from_ymd_opt(2024, 6, 12));
+        assert!(!todo.is_completed);
+    }
+
+    #[test]
+    fn completed_todos_are_recognized() {
+        let calendar = "BEGIN:VCALENDAR\nBEGIN:VTODO\nSUMMARY:Dishes\nDUE:20240610T120000Z\n\
+            STATUS:COMPLETED\nEND:VTODO\nEND:VCALENDAR\n";
+        let todo = parse_todo(calendar).unwrap();
+
+        assert!(todo.is_completed);
+        assert_eq!(todo.due, NaiveDate::from_ymd_opt(2024, 6, 10));
+        assert!(parse_todo("BEGIN:VCALENDAR\nEND:VCALENDAR\n").is_none());
+    }
+
+    #[test]
+    fn folded_lines_and_parameters_are_read() {
+        let calendar = "BEGIN:VCALENDAR\r\nSUMMARY:Not the todo\r\nBEGIN:VTODO\r\n\
+            SUMMARY;LANGUAGE=en:Water \r\n the plants\r\nDUE;VALUE=DATE:20240612\r\n\
+            END:VTODO\r\nEND:VCALENDAR\r\n";
+        let todo = parse_todo(calendar).unwrap();
+
+        assert_eq!(todo.summary.as_deref(), Some("Water the plants"));
+        assert_eq!(todo.due, NaiveDate::from_ymd_opt(2024, 6, 12));
+        assert!(!todo.is_completed);
+    }
+
+    #[test]
+    fn missing_and_broken_values_are_left_out() {
+        let calendar = "BEGIN:VTODO\nDUE:2024-06-12\nCOMPLETED:20240610T120000Z\nEND:VTODO\n";
+        let todo = parse_todo(calendar).unwrap();
+
+        assert_eq!(todo.summary, None);
+        assert_eq!(todo.due, None);
+        assert!(todo.is_completed);
+    }
+
+    #[test]
+    fn etags_follow_the_content() {
+        let tag = etag("a");
+        assert_eq!(tag, etag("a"));
+        assert_ne!(tag, etag("b"));
+        assert!(tag.starts_with('"') && tag.ends_with('"'));
+    }
+}
@@ -0,0 +1,525 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Importers for the exports of other task managers. Only recurring tasks map onto upkeep, every
+//! other task is reported back as skipped so it can be moved somewhere else by hand.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use super::transfer::{ParsedRow, ParsedUpload, SkippedTask, TransferItem};
+
+const NOT_RECURRING: &str = "Not recurring";
+/// The longest cooldown an item can have, the same ten years the form allows.
+const MAX_COOLDOWN_DAYS: i32 = 3650;
+
+/// The number of days in a single unit, for both Taskwarrior durations and Todoist recurrences.
+fn unit_days(unit: &str) -> Option<i32> {
+    match unit {
+        "d" | "day" | "days" | "weekday" | "weekdays" | "workday" | "workdays" => Some(1),
+        "w" | "wk" | "wks" | "week" | "weeks" => Some(7),
+        "fortnight" | "fortnights" => Some(14),
+        "mo" | "mos" | "mth" | "mths" | "month" | "months" => Some(30),
+        "q" | "qtr" | "qtrs" | "quarter" | "quarters" => Some(91),
+        "y" | "yr" | "yrs" | "year" | "years" => Some(365),
+        _ => None,
+    }
+}
+
+fn is_sub_day_unit(unit: &str) -> bool {
+    matches!(
+        unit,
+        "h" | "hr"
+            | "hrs"
+            | "hour"
+            | "hours"
+            | "min"
+            | "mins"
+            | "minute"
+            | "minutes"
+            | "s"
+            | "sec"
+            | "secs"
+            | "second"
+            | "seconds"
+    )
+}
+
+fn unsupported(recurrence: &str) -> String {
+    format!("Unsupported recurrence \"{}\"", recurrence)
+}
+
+fn multiply(count: i32, unit: &str, recurrence: &str) -> Result<i32, String> {
+    if is_sub_day_unit(unit) {
+        return Err(format!(
+            "Recurs more than once a day (\"{}\"), which is too often for upkeep",
+            recurrence
+        ));
+    }
+    match unit_days(unit) {
+        Some(days) if count > 0 => count
+            .checked_mul(days)
+            .filter(|days| *days <= MAX_COOLDOWN_DAYS)
+            .ok_or_else(|| unsupported(recurrence)),
+        _ => Err(unsupported(recurrence)),
+    }
+}
+
+/// Turns a Taskwarrior `recur` value into a cooldown, e.g. `weekly`, `3d`, `2wks` or `P1M`.
+fn taskwarrior_cooldown(recur: &str) -> Result<i32, String> {
+    let normalized = recur.trim().to_lowercase();
+    let named = match normalized.as_str() {
+        "daily" => Some(1),
+        "weekly" => Some(7),
+        "biweekly" => Some(14),
+        "monthly" => Some(30),
+        "bimonthly" => Some(61),
+        "quarterly" => Some(91),
+        "semiannual" => Some(183),
+        "annual" | "yearly" => Some(365),
+        "biannual" | "biyearly" => Some(730),
+        _ => None,
+    };
+    if let Some(days) = named {
+        return Ok(days);
+    }
+
+    // ISO 8601 durations, only the date part is meaningful for upkeep.
+    let (duration, is_iso) = match normalized.strip_prefix('p') {
+        Some(rest) if !rest.contains('t') => (rest, true),
+        Some(_) => return multiply(1, "h", recur),
+        None => (normalized.as_str(), false),
+    };
+    let split = duration
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (count, unit) = duration.split_at(split);
+    let count = match count {
+        "" => 1,
+        count => count.parse().map_err(|_| unsupported(recur))?,
+    };
+    // Before the time part of an ISO duration, M stands for months.
+    let unit = match (is_iso, unit.trim()) {
+        (true, "m") => "mo",
+        (_, unit) => unit,
+    };
+    multiply(count, unit, recur)
+}
+
+/// Turns a Todoist due string into a cooldown. Returns `None` if the task doesn't recur at all.
+/// Anything after the interval, like "starting mon" or "at 9am", doesn't change the cooldown.
+fn todoist_cooldown(date: &str) -> Option<Result<i32, String>> {
+    let normalized = date.trim().to_lowercase();
+    let words: Vec<&str> = normalized
+        .split(|character: char| character.is_whitespace() || character == ',')
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let (first, rest) = words.split_first()?;
+    let rest = match *first {
+        "daily" => return Some(Ok(1)),
+        "weekly" => return Some(Ok(7)),
+        "monthly" => return Some(Ok(30)),
+        "yearly" | "annually" => return Some(Ok(365)),
+        "every" | "every!" | "ev" | "ev!" => rest,
+        _ => return None,
+    };
+
+    const WEEKDAYS: [&str; 14] = [
+        "mon",
+        "tue",
+        "wed",
+        "thu",
+        "fri",
+        "sat",
+        "sun",
+        "monday",
+        "tuesday",
+        "wednesday",
+        "thursday",
+        "friday",
+        "saturday",
+        "sunday",
+    ];
+    let weekdays = rest
+        .iter()
+        .take_while(|word| WEEKDAYS.contains(word) || **word == "and")
+        .filter(|word| **word != "and")
+        .count() as i32;
+    if weekdays > 0 {
+        return Some(Ok((7 / weekdays).max(1)));
+    }
+
+    let result = match rest {
+        ["other", unit, ..] => multiply(2, unit, date),
+        [count, unit, ..] if count.parse::<i32>().is_ok() => {
+            multiply(count.parse().unwrap_or_default(), unit, date)
+        }
+        [unit, ..] if unit_days(unit).is_some() || is_sub_day_unit(unit) => multiply(1, unit, date),
+        // Day of the month, like "every 15th" or "every last day".
+        [day, ..] if day.ends_with("st") || day.ends_with("nd") || day.ends_with("rd") => Ok(30),
+        [day, ..] if day.ends_with("th") || *day == "last" || *day == "first" => Ok(30),
+        _ => Err(unsupported(date)),
+    };
+    Some(result)
+}
+
+/// Errors name the task, a row that couldn't be read has no description of its own.
+fn recurring_item(
+    description: String,
+    cooldown_days: Result<i32, String>,
+) -> Result<TransferItem, String> {
+    match cooldown_days {
+        Ok(cooldown_days) => Ok(TransferItem {
+            description,
+            cooldown_days,
+            ..Default::default()
+        }),
+        Err(error) => Err(format!("{}: {}", description, error)),
+    }
+}
+
+#[derive(Deserialize)]
+struct TaskwarriorTask {
+    uuid: String,
+    description: String,
+    status: String,
+    #[serde(default)]
+    recur: Option<String>,
+    #[serde(default)]
+    due: Option<String>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+/// Taskwarrior writes dates like `20240131T230000Z`.
+fn taskwarrior_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+struct RecurringTask {
+    line: usize,
+    description: String,
+    recur: String,
+    due: Option<NaiveDate>,
+    has_ended: bool,
+}
+
+/// Reads the output of `task export`. Older versions print one object per line instead of a
+/// list, both are accepted.
+pub fn parse_taskwarrior(data: &str) -> Result<ParsedUpload> {
+    let values: Vec<serde_json::Value> = if data.trim_start().starts_with('[') {
+        serde_json::from_str(data)?
+    } else {
+        data.lines()
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut rows = Vec::new();
+    let mut skipped = Vec::new();
+    // Recurring tasks are a template plus an instance per period, grouped on the template.
+    let mut recurring: Vec<RecurringTask> = Vec::new();
+    let mut by_uuid: HashMap<String, usize> = HashMap::new();
+
+    for (index, value) in values.into_iter().enumerate() {
+        let line = index + 1;
+        let task: TaskwarriorTask = match serde_json::from_value(value) {
+            Ok(task) => task,
+            Err(error) => {
+                rows.push(ParsedRow {
+                    line,
+                    item: Err(error.to_string()),
+                });
+                continue;
+            }
+        };
+
+        let Some(recur) = task.recur else {
+            if matches!(task.status.as_str(), "pending" | "waiting") {
+                skipped.push(SkippedTask {
+                    line,
+                    description: task.description,
+                    reason: NOT_RECURRING.into(),
+                });
+            }
+            continue;
+        };
+
+        let is_template = task.parent.is_none();
+        let key = task.parent.unwrap_or(task.uuid);
+        let position = *by_uuid.entry(key).or_insert_with(|| {
+            recurring.push(RecurringTask {
+                line,
+                description: task.description.clone(),
+                recur: recur.clone(),
+                due: None,
+                has_ended: false,
+            });
+            recurring.len() - 1
+        });
+        let group = &mut recurring[position];
+        match task.status.as_str() {
+            "recurring" => {
+                group.description = task.description;
+                group.recur = recur;
+            }
+            "pending" | "waiting" => {
+                // The earliest open instance is the next time it's due.
+                if let Some(due) = task.due.as_deref().and_then(taskwarrior_date) {
+                    group.due = Some(group.due.map_or(due, |current| current.min(due)));
+                }
+            }
+            // Deleting the template stops the recurrence.
+            "deleted" if is_template => group.has_ended = true,
+            _ => {}
+        }
+    }
+
+    for task in recurring {
+        if task.has_ended {
+            continue;
+        }
+        rows.push(ParsedRow {
+            line: task.line,
+            item: recurring_item(task.description, taskwarrior_cooldown(&task.recur)).map(|item| {
+                TransferItem {
+                    due: task.due,
+                    ..item
+                }
+            }),
+        });
+    }
+    rows.sort_by_key(|row| row.line);
+
+    Ok(ParsedUpload {
+        rows: rows.into(),
+        skipped: skipped.into(),
+    })
+}
+
+/// Reads a project from a Todoist backup. Only rows of type `task` are used, sections and notes
+/// are left out.
+pub fn parse_todoist(data: &str) -> Result<ParsedUpload> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+    };
+    let (Some(kind), Some(content), Some(date)) =
+        (column("TYPE"), column("CONTENT"), column("DATE"))
+    else {
+        return Err(anyhow!(
+            "this is not a Todoist backup, the TYPE, CONTENT and DATE columns are missing"
+        ));
+    };
+    let duration = column("DURATION");
+    let duration_unit = column("DURATION_UNIT");
+
+    let mut rows = Vec::new();
+    let mut skipped = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let field = |index: usize| record.get(index).unwrap_or_default().trim();
+        if !field(kind).eq_ignore_ascii_case("task") {
+            continue;
+        }
+
+        let description = field(content).to_string();
+        let cooldown_days = match todoist_cooldown(field(date)) {
+            Some(cooldown_days) => cooldown_days,
+            None => {
+                skipped.push(SkippedTask {
+                    line,
+                    description,
+                    reason: NOT_RECURRING.into(),
+                });
+                continue;
+            }
+        };
+
+        let effort_minutes = match (duration, duration_unit) {
+            (Some(duration), Some(unit)) if field(unit).eq_ignore_ascii_case("minute") => {
+                field(duration).parse().ok()
+            }
+            _ => None,
+        };
+        rows.push(ParsedRow {
+            line,
+            item: recurring_item(description, cooldown_days).map(|item| TransferItem {
+                effort_minutes,
+                ..item
+            }),
+        });
+    }
+
+    Ok(ParsedUpload {
+        rows: rows.into(),
+        skipped: skipped.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line, and the description and cooldown or error of a row.
+    type Row<'a> = (usize, Result<(&'a str, i32), &'a str>);
+
+    fn cooldowns(upload: &ParsedUpload) -> Vec<Row<'_>> {
+        upload
+            .rows
+            .iter()
+            .map(|row| {
+                let item = match &row.item {
+                    Ok(item) => Ok((item.description.as_str(), item.cooldown_days)),
+                    Err(error) => Err(error.as_str()),
+                };
+                (row.line, item)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn taskwarrior_recurrences() {
+        assert_eq!(taskwarrior_cooldown("weekly"), Ok(7));
+        assert_eq!(taskwarrior_cooldown(" Quarterly "), Ok(91));
+        assert_eq!(taskwarrior_cooldown("3d"), Ok(3));
+        assert_eq!(taskwarrior_cooldown("2wks"), Ok(14));
+        assert_eq!(taskwarrior_cooldown("month"), Ok(30));
+        assert_eq!(taskwarrior_cooldown("P1M"), Ok(30));
+        assert_eq!(taskwarrior_cooldown("P2W"), Ok(14));
+    }
+
+    #[test]
+    fn taskwarrior_recurrences_that_do_not_fit() {
+        assert!(taskwarrior_cooldown("12h")
+            .unwrap_err()
+            .contains("more than once a day"));
+        assert!(taskwarrior_cooldown("PT6H")
+            .unwrap_err()
+            .contains("more than once a day"));
+        assert!(taskwarrior_cooldown("0d").is_err());
+        assert!(taskwarrior_cooldown("fortnightly-ish").is_err());
+    }
+
+    #[test]
+    fn huge_recurrences_are_unsupported() {
+        assert_eq!(
+            taskwarrior_cooldown("999999999y"),
+            Err(unsupported("999999999y"))
+        );
+        assert_eq!(
+            taskwarrior_cooldown("99999999999d"),
+            Err(unsupported("99999999999d"))
+        );
+        assert_eq!(taskwarrior_cooldown("11y"), Err(unsupported("11y")));
+        assert_eq!(taskwarrior_cooldown("10y"), Ok(3650));
+        assert_eq!(
+            todoist_cooldown("every 2000000000 years"),
+            Some(Err(unsupported("every 2000000000 years")))
+        );
+    }
+
+    #[test]
+    fn todoist_recurrences() {
+        assert_eq!(todoist_cooldown("every day"), Some(Ok(1)));
+        assert_eq!(todoist_cooldown("Every 3 days at 9am"), Some(Ok(3)));
+        assert_eq!(todoist_cooldown("every other week"), Some(Ok(14)));
+        assert_eq!(todoist_cooldown("every! 2 months"), Some(Ok(60)));
+        assert_eq!(todoist_cooldown("every mon, wed and fri"), Some(Ok(2)));
+        assert_eq!(todoist_cooldown("every 15th"), Some(Ok(30)));
+        assert_eq!(todoist_cooldown("yearly"), Some(Ok(365)));
+        assert!(todoist_cooldown("every hour").unwrap().is_err());
+        assert!(todoist_cooldown("every blue moon").unwrap().is_err());
+        assert_eq!(todoist_cooldown("tomorrow"), None);
+        assert_eq!(todoist_cooldown(""), None);
+    }
+
+    #[test]
+    fn taskwarrior_instances_are_grouped_on_their_template() {
+        let export = r#"[
+            {"uuid": "a", "description": "Water plants", "status": "recurring", "recur": "weekly"},
+            {"uuid": "b", "description": "Water plants", "status": "completed", "recur": "weekly", "parent": "a", "due": "20240603T000000Z"},
+            {"uuid": "c", "description": "Water plants", "status": "pending", "recur": "weekly", "parent": "a", "due": "20240610T000000Z"},
+            {"uuid": "d", "description": "Call mom", "status": "pending"},
+            {"uuid": "e", "description": "Old chore", "status": "deleted", "recur": "daily"},
+            {"uuid": "f", "description": "Too often", "status": "recurring", "recur": "4h"}
+        ]"#;
+        let upload = parse_taskwarrior(export).unwrap();
+
+        let rows = cooldowns(&upload);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], (1, Ok(("Water plants", 7))));
+        assert!(matches!(rows[1], (6, Err(error)) if error.starts_with("Too often: ")));
+        assert_eq!(
+            upload.rows[0].item.as_ref().unwrap().due,
+            NaiveDate::from_ymd_opt(2024, 6, 10)
+        );
+        assert_eq!(upload.skipped.len(), 1);
+        assert_eq!(upload.skipped[0].description, "Call mom");
+    }
+
+    #[test]
+    fn taskwarrior_accepts_one_task_per_line() {
+        let export = concat!(
+            r#"{"uuid": "a", "description": "Sweep", "status": "recurring", "recur": "3d"},"#,
+            "\n",
+            r#"{"uuid": "b", "description": "Mop", "status": "recurring", "recur": "P2W"}"#,
+        );
+        let upload = parse_taskwarrior(export).unwrap();
+
+        assert_eq!(
+            cooldowns(&upload),
+            vec![(1, Ok(("Sweep", 3))), (2, Ok(("Mop", 14)))]
+        );
+    }
+
+    #[test]
+    fn todoist_backups() {
+        let backup = "TYPE,CONTENT,DATE,DURATION,DURATION_UNIT\n\
+            section,Chores,,,\n\
+            task,Vacuum,every week,30,minute\n\
+            task,Dentist,tomorrow,,\n\
+            task,Descale kettle,every 3 months,,\n";
+        let upload = parse_todoist(backup).unwrap();
+
+        assert_eq!(
+            cooldowns(&upload),
+            vec![(3, Ok(("Vacuum", 7))), (5, Ok(("Descale kettle", 90)))]
+        );
+        assert_eq!(
+            upload.rows[0].item.as_ref().unwrap().effort_minutes,
+            Some(30)
+        );
+        assert_eq!(upload.skipped[0].description, "Dentist");
+        assert!(parse_todoist("title,due\nVacuum,every week\n").is_err());
+    }
+}
@@ -0,0 +1,575 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use super::database::{FetchUpkeepCapacity, FetchUpkeepDone, FetchUpkeepItem};
+
+#[derive(Clone)]
+pub struct PlannedChange {
+    pub id: i32,
+    pub description: Arc<str>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+pub fn overdue_ratio(item: &FetchUpkeepItem, today: NaiveDate) -> f64 {
+    let days_overdue = (today - item.due).num_days() + 1;
+    days_overdue as f64 / item.cooldown_days.max(1) as f64
+}
+
+pub fn plan_catch_up(
+    items: &[FetchUpkeepItem],
+    today: NaiveDate,
+    days: usize,
+    daily_limit: usize,
+) -> Box<[PlannedChange]> {
+    if days == 0 {
+        return Box::from([]);
+    }
+
+    let mut load = vec![0usize; days];
+    for item in items.iter().filter(|item| item.paused_since.is_none()) {
+        let offset = (item.due - today).num_days();
+        if offset > 0 && (offset as usize) < days {
+            load[offset as usize] += 1;
+        }
+    }
+
+    let mut overdue: Vec<_> = items
+        .iter()
+        .filter(|item| item.paused_since.is_none() && item.due <= today)
+        .collect();
+    overdue.sort_by(|a, b| {
+        overdue_ratio(b, today)
+            .total_cmp(&overdue_ratio(a, today))
+            .then(a.cooldown_days.cmp(&b.cooldown_days))
+    });
+
+    overdue
+        .into_iter()
+        .filter_map(|item| {
+            // Never push an item further than its own cooldown, it would be due again by then.
+            let latest = (item.cooldown_days.max(1) as usize).min(days) - 1;
+            let offset = (0..=latest)
+                .find(|&offset| load[offset] < daily_limit)
+                .unwrap_or_else(|| (0..=latest).min_by_key(|&offset| load[offset]).unwrap_or(0));
+            load[offset] += 1;
+
+            let to = today + Duration::days(offset as i64);
+            (to != item.due).then(|| PlannedChange {
+                id: item.id,
+                description: item.description.clone(),
+                from: item.due,
+                to,
+            })
+        })
+        .collect()
+}
+
+pub fn focus_candidates(items: &[FetchUpkeepItem], today: NaiveDate) -> Box<[&FetchUpkeepItem]> {
+    let mut candidates: Vec<_> = items
+        .iter()
+        .filter(|item| item.paused_since.is_none() && item.due <= today)
+        .collect();
+    candidates.sort_by(|a, b| overdue_ratio(b, today).total_cmp(&overdue_ratio(a, today)));
+    candidates.into()
+}
+
+/// Splits the due items into what still fits in today's capacity and what waits. Whatever was
+/// already `done` today counts against the capacity as well.
+pub fn fit_capacity<'a>(
+    due_items: &[&'a FetchUpkeepItem],
+    capacity: &FetchUpkeepCapacity,
+    done: &FetchUpkeepDone,
+) -> (Box<[&'a FetchUpkeepItem]>, Box<[&'a FetchUpkeepItem]>) {
+    let item_limit = capacity.daily_item_limit.map_or(i64::MAX, i64::from);
+    let minute_limit = capacity.daily_minute_limit.map_or(i64::MAX, i64::from);
+
+    let mut items = done.items;
+    let mut minutes = done.minutes;
+    let mut fitting = Vec::new();
+    let mut waiting = Vec::new();
+    for item in due_items {
+        let effort = item.effort_minutes.unwrap_or(0) as i64;
+        // The first item of the day always fits, a large item should never be hidden forever.
+        if items == 0 || (items < item_limit && minutes + effort <= minute_limit) {
+            items += 1;
+            minutes += effort;
+            fitting.push(*item);
+        } else {
+            waiting.push(*item);
+        }
+    }
+
+    (fitting.into(), waiting.into())
+}
+
+pub struct ForecastDay {
+    pub date: NaiveDate,
+    pub items: usize,
+    pub minutes: i32,
+}
+
+fn occurrence_offsets(first: i64, cooldown_days: i32, days: i64) -> impl Iterator<Item = i64> {
+    let step = cooldown_days.max(1) as i64;
+    (0..)
+        .map(move |occurrence| first + occurrence * step)
+        .take_while(move |&offset| offset < days)
+}
+
+pub fn forecast(
+    items: &[FetchUpkeepItem],
+    today: NaiveDate,
+    days: usize,
+    changes: &[PlannedChange],
+) -> Box<[ForecastDay]> {
+    let mut forecast: Vec<_> = (0..days)
+        .map(|offset| ForecastDay {
+            date: today + Duration::days(offset as i64),
+            items: 0,
+            minutes: 0,
+        })
+        .collect();
+
+    for item in items.iter().filter(|item| item.paused_since.is_none()) {
+        let due = changes
+            .iter()
+            .find(|change| change.id == item.id)
+            .map_or(item.due, |change| change.to);
+        let first = (due - today).num_days().max(0);
+        for offset in occurrence_offsets(first, item.cooldown_days, days as i64) {
+            let day = &mut forecast[offset as usize];
+            day.items += 1;
+            day.minutes += item.effort_minutes.unwrap_or(0);
+        }
+    }
+
+    forecast.into()
+}
+
+const MAX_LEVEL_PASSES: usize = 10;
+
+pub fn level_schedule(
+    items: &[FetchUpkeepItem],
+    today: NaiveDate,
+    days: usize,
+    tolerance: i64,
+) -> Box<[PlannedChange]> {
+    // Look past the horizon by the tolerance, so items aren't pushed out of view to look lighter.
+    let window = days as i64 + tolerance;
+    let items: Vec<_> = items
+        .iter()
+        .filter(|item| item.paused_since.is_none())
+        .collect();
+    let mut shifts = vec![0i64; items.len()];
+    let first = |item: &FetchUpkeepItem, shift: i64| ((item.due - today).num_days() + shift).max(0);
+
+    let mut load = vec![0usize; window as usize];
+    for item in items.iter() {
+        for offset in occurrence_offsets(first(item, 0), item.cooldown_days, window) {
+            load[offset as usize] += 1;
+        }
+    }
+
+    // Rare items are the easiest to move without anyone noticing, so they get moved first.
+    let mut flexible: Vec<_> = (0..items.len())
+        .filter(|&index| items[index].due > today && items[index].cooldown_days > 2)
+        .collect();
+    flexible.sort_by_key(|&index| -items[index].cooldown_days);
+
+    for _ in 0..MAX_LEVEL_PASSES {
+        let mut changed = false;
+        for &index in flexible.iter() {
+            let item = items[index];
+            let limit = tolerance.min(((item.cooldown_days - 1) / 2) as i64);
+            let days_until_due = (item.due - today).num_days();
+
+            for offset in occurrence_offsets(first(item, shifts[index]), item.cooldown_days, window)
+            {
+                load[offset as usize] -= 1;
+            }
+
+            let cost = |shift: i64| {
+                let (total, count) =
+                    occurrence_offsets(first(item, shift), item.cooldown_days, window)
+                        .fold((0, 0), |(total, count), offset| {
+                            (total + load[offset as usize], count + 1)
+                        });
+                match count {
+                    0 => 0.0,
+                    count => total as f64 / count as f64,
+                }
+            };
+
+            let current = shifts[index];
+            let best = (-limit..=limit)
+                .filter(|shift| days_until_due + shift > 0)
+                .min_by(|a, b| cost(*a).total_cmp(&cost(*b)).then(a.abs().cmp(&b.abs())))
+                .unwrap_or(current);
+            if best != current && cost(best) < cost(current) {
+                shifts[index] = best;
+                changed = true;
+            }
+
+            for offset in occurrence_offsets(first(item, shifts[index]), item.cooldown_days, window)
+            {
+                load[offset as usize] += 1;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    items
+        .iter()
+        .zip(shifts)
+        .filter(|(_, shift)| *shift != 0)
+        .map(|(item, shift)| PlannedChange {
+            id: item.id,
+            description: item.description.clone(),
+            from: item.due,
+            to: item.due + Duration::days(shift),
+        })
+        .collect()
+}
+
+const SUGGESTION_INTERVALS: usize = 8;
+const MINIMUM_SUGGESTION_INTERVALS: usize = 3;
+/// The completions `suggest_cooldown` looks at, older ones don't have to be fetched.
+pub const SUGGESTION_COMPLETIONS: usize = SUGGESTION_INTERVALS + 1;
+
+pub fn suggest_cooldown(cooldown_days: i32, completions: &[NaiveDateTime]) -> Option<i32> {
+    let start = completions.len().saturating_sub(SUGGESTION_COMPLETIONS);
+    let mut intervals: Vec<_> = completions[start..]
+        .windows(2)
+        .map(|pair| (pair[1].date() - pair[0].date()).num_days() as i32)
+        .collect();
+
+    if intervals.len() < MINIMUM_SUGGESTION_INTERVALS {
+        return None;
+    }
+
+    intervals.sort_unstable();
+    let middle = intervals.len() / 2;
+    let median = match intervals.len() % 2 {
+        0 => (intervals[middle - 1] + intervals[middle] + 1) / 2,
+        _ => intervals[middle],
+    };
+
+    // Small differences are noise, only suggest when the real rhythm is clearly different.
+    let difference = (median - cooldown_days).abs();
+    (median > 0 && difference >= 2 && difference * 2 >= cooldown_days).then_some(median)
+}
+
+pub fn encode_changes(changes: &[PlannedChange]) -> Box<str> {
+    changes
+        .iter()
+        .map(|change| format!("{}:{}", change.id, change.to))
+        .collect::<Vec<_>>()
+        .join(",")
+        .into()
+}
+
+pub fn decode_changes(changes: &str) -> Result<Box<[(i32, NaiveDate)]>> {
+    changes
+        .split(',')
+        .filter(|change| !change.is_empty())
+        .map(|change| {
+            let (id, due) = change
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Malformed change '{}'", change))?;
+            Ok((id.parse()?, due.parse()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::sections::upkeep::database::{AssignmentMode, ResumePolicy};
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 10).unwrap()
+    }
+
+    /// An item due `due` days from today, negative for overdue items.
+    fn item(id: i32, due: i64, cooldown_days: i32) -> FetchUpkeepItem {
+        FetchUpkeepItem {
+            id,
+            description: format!("item {}", id).into(),
+            cooldown_days,
+            due: today() + Duration::days(due),
+            paused_since: None,
+            resume_policy: ResumePolicy::Shift,
+            effort_minutes: None,
+            window_days: 0,
+            household_id: None,
+            assignment_mode: AssignmentMode::Rotate,
+            assigned_account_id: None,
+            revision: 0,
+            updated_at: today().and_time(NaiveTime::MIN),
+        }
+    }
+
+    fn planned(changes: &[PlannedChange]) -> Vec<(i32, i64)> {
+        let mut planned: Vec<_> = changes
+            .iter()
+            .map(|change| (change.id, (change.to - today()).num_days()))
+            .collect();
+        planned.sort();
+        planned
+    }
+
+    #[test]
+    fn catch_up_spreads_overdue_items_over_the_days() {
+        let items = [
+            item(1, -3, 7),
+            item(2, -3, 7),
+            item(3, -3, 7),
+            item(4, -3, 7),
+        ];
+        let changes = plan_catch_up(&items, today(), 7, 2);
+        let mut per_day = [0; 7];
+        for (_, offset) in planned(&changes) {
+            per_day[offset as usize] += 1;
+        }
+        assert_eq!(per_day, [2, 2, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn catch_up_plans_frequent_and_most_overdue_items_first() {
+        let items = [item(1, -1, 30), item(2, -6, 3)];
+        let changes = plan_catch_up(&items, today(), 7, 1);
+        assert_eq!(planned(&changes), [(1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn catch_up_never_plans_past_the_cooldown() {
+        let items = [item(1, -1, 1), item(2, -1, 1), item(3, -1, 2)];
+        let changes = plan_catch_up(&items, today(), 7, 1);
+        for (id, offset) in planned(&changes) {
+            let cooldown = items
+                .iter()
+                .find(|item| item.id == id)
+                .unwrap()
+                .cooldown_days;
+            assert!(
+                offset < cooldown as i64,
+                "item {} planned at {}",
+                id,
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn catch_up_counts_items_already_due_on_later_days() {
+        let items = [item(1, -2, 7), item(2, 1, 7)];
+        let changes = plan_catch_up(&items, today(), 7, 1);
+        assert_eq!(planned(&changes), [(1, 0)]);
+
+        // Due today counts as overdue, the item that is further behind keeps today.
+        let items = [item(1, 0, 7), item(2, -2, 7), item(3, 1, 7)];
+        let changes = plan_catch_up(&items, today(), 7, 1);
+        assert_eq!(planned(&changes), [(1, 2), (2, 0)]);
+    }
+
+    #[test]
+    fn catch_up_leaves_paused_items_alone() {
+        let mut paused = item(1, -5, 7);
+        paused.paused_since = Some(today() - Duration::days(1));
+        assert!(plan_catch_up(&[paused], today(), 7, 1).is_empty());
+    }
+
+    fn with_effort(mut item: FetchUpkeepItem, effort_minutes: i32) -> FetchUpkeepItem {
+        item.effort_minutes = Some(effort_minutes);
+        item
+    }
+
+    fn capacity(items: Option<i32>, minutes: Option<i32>) -> FetchUpkeepCapacity {
+        FetchUpkeepCapacity {
+            daily_item_limit: items,
+            daily_minute_limit: minutes,
+        }
+    }
+
+    fn done(items: i64, minutes: i64) -> FetchUpkeepDone {
+        FetchUpkeepDone { items, minutes }
+    }
+
+    fn ids(items: &[&FetchUpkeepItem]) -> Vec<i32> {
+        items.iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn capacity_limits_items_and_minutes() {
+        let items = [
+            with_effort(item(1, 0, 7), 20),
+            with_effort(item(2, 0, 7), 20),
+            with_effort(item(3, 0, 7), 20),
+        ];
+        let due: Vec<_> = items.iter().collect();
+
+        let (now, waiting) = fit_capacity(&due, &capacity(Some(2), None), &done(0, 0));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![1, 2], vec![3]));
+
+        let (now, waiting) = fit_capacity(&due, &capacity(None, Some(45)), &done(0, 0));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![1, 2], vec![3]));
+
+        let (now, waiting) = fit_capacity(&due, &capacity(None, None), &done(0, 0));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![1, 2, 3], vec![]));
+    }
+
+    #[test]
+    fn capacity_counts_what_was_done_today() {
+        let items = [
+            with_effort(item(1, 0, 7), 20),
+            with_effort(item(2, 0, 7), 20),
+        ];
+        let due: Vec<_> = items.iter().collect();
+
+        let (now, waiting) = fit_capacity(&due, &capacity(Some(2), None), &done(1, 0));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![1], vec![2]));
+
+        let (now, waiting) = fit_capacity(&due, &capacity(None, Some(45)), &done(1, 30));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![], vec![1, 2]));
+
+        let (now, waiting) = fit_capacity(&due, &capacity(Some(2), None), &done(2, 0));
+        assert_eq!((ids(&now), ids(&waiting)), (vec![], vec![1, 2]));
+    }
+
+    #[test]
+    fn capacity_always_fits_the_first_item_of_the_day() {
+        let items = [with_effort(item(1, 0, 7), 90)];
+        let due: Vec<_> = items.iter().collect();
+
+        let (now, _) = fit_capacity(&due, &capacity(None, Some(30)), &done(0, 0));
+        assert_eq!(ids(&now), vec![1]);
+
+        let (now, _) = fit_capacity(&due, &capacity(None, Some(30)), &done(1, 10));
+        assert_eq!(ids(&now), Vec::<i32>::new());
+    }
+
+    fn loads(forecast: &[ForecastDay]) -> Vec<usize> {
+        forecast.iter().map(|day| day.items).collect()
+    }
+
+    #[test]
+    fn forecast_repeats_items_by_their_cooldown() {
+        let items = [with_effort(item(1, 1, 3), 20), item(2, -4, 5)];
+        let forecast = forecast(&items, today(), 7, &[]);
+
+        // Overdue items are projected on today.
+        assert_eq!(loads(&forecast), vec![1, 1, 0, 0, 1, 1, 0]);
+        assert_eq!(forecast[1].minutes, 20);
+        assert_eq!(forecast[0].date, today());
+    }
+
+    #[test]
+    fn forecast_applies_planned_changes_and_skips_paused_items() {
+        let mut paused = item(2, 0, 7);
+        paused.paused_since = Some(today());
+        let items = [item(1, 1, 7), paused];
+        let changes = [PlannedChange {
+            id: 1,
+            description: "item 1".into(),
+            from: today() + Duration::days(1),
+            to: today() + Duration::days(3),
+        }];
+
+        assert_eq!(
+            loads(&forecast(&items, today(), 5, &changes)),
+            vec![0, 0, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn levelling_spreads_a_pile_up() {
+        let items = [
+            item(1, 3, 14),
+            item(2, 3, 14),
+            item(3, 3, 14),
+            item(4, 3, 14),
+        ];
+        let changes = level_schedule(&items, today(), 14, 2);
+        let forecast = forecast(&items, today(), 14, &changes);
+
+        assert!(loads(&forecast).iter().all(|&load| load <= 2));
+        assert!(changes
+            .iter()
+            .all(|change| change.from == today() + Duration::days(3)));
+        assert!(changes
+            .iter()
+            .all(|change| (change.to - change.from).num_days().abs() <= 2));
+    }
+
+    #[test]
+    fn levelling_leaves_due_and_frequent_items_alone() {
+        let items = [item(1, 0, 14), item(2, 0, 14), item(3, 1, 2), item(4, 1, 2)];
+        assert!(level_schedule(&items, today(), 14, 3).is_empty());
+    }
+
+    #[test]
+    fn levelling_never_moves_items_to_today() {
+        let items = [item(1, 1, 30), item(2, 1, 30), item(3, 1, 30)];
+        let changes = level_schedule(&items, today(), 14, 5);
+
+        assert!(!changes.is_empty());
+        assert!(changes.iter().all(|change| change.to > today()));
+    }
+
+    #[test]
+    fn levelling_keeps_shifts_within_half_the_cooldown() {
+        let items = [item(1, 2, 4), item(2, 2, 4), item(3, 2, 4)];
+        let changes = level_schedule(&items, today(), 14, 5);
+
+        assert!(changes
+            .iter()
+            .all(|change| (change.to - change.from).num_days().abs() <= 1));
+    }
+
+    #[test]
+    fn changes_survive_encoding() {
+        let changes = level_schedule(
+            &[item(1, 3, 14), item(2, 3, 14), item(3, 3, 14)],
+            today(),
+            14,
+            2,
+        );
+        let decoded = decode_changes(&encode_changes(&changes)).unwrap();
+
+        assert_eq!(
+            decoded.to_vec(),
+            changes
+                .iter()
+                .map(|change| (change.id, change.to))
+                .collect::<Vec<_>>()
+        );
+        assert!(decode_changes("").unwrap().is_empty());
+        assert!(decode_changes("1-2024-06-10").is_err());
+        assert!(decode_changes("x:2024-06-10").is_err());
+    }
+}
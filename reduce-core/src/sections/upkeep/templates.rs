@@ -19,8 +19,45 @@
 use std::sync::Arc;
 
 use askama::Template;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
 
-use crate::extensions::{AuthorizedSession, Session};
+use crate::{
+    extensions::{AuthorizedSession, Session},
+    validation::FormState,
+};
+
+use super::{
+    database::{AssignmentMode, ResumePolicy},
+    schedule::{ForecastDay, PlannedChange},
+};
+
+#[derive(Clone)]
+pub struct PartStep {
+    pub id: i32,
+    pub description: Arc<str>,
+    pub checked: bool,
+}
+
+#[derive(Clone)]
+pub struct PartHousehold {
+    pub id: i32,
+    pub name: Arc<str>,
+    pub members: Box<[Arc<str>]>,
+}
+
+pub struct PartHouseholdInvite {
+    pub household_id: i32,
+    pub household: Arc<str>,
+    pub invited_by: Arc<str>,
+}
+
+pub struct PartHouseholdCompletion {
+    pub household: Arc<str>,
+    pub description: Arc<str>,
+    pub completed_by: Arc<str>,
+    pub completed_at: Box<str>,
+}
 
 #[derive(Clone)]
 pub struct PartItem {
@@ -28,14 +65,161 @@ pub struct PartItem {
     pub description: Arc<str>,
     pub due: Arc<str>,
     pub cooldown: Arc<str>,
+    pub effort: Option<Arc<str>>,
+    pub suggested_cooldown: Option<i32>,
     pub render_complete: bool,
+    pub is_available: bool,
+    pub is_urgent: bool,
+    pub paused: bool,
+    pub resume_policy: ResumePolicy,
+    pub steps: Box<[PartStep]>,
+    pub next_step: Option<PartStep>,
+    pub steps_done: usize,
+    pub household_id: Option<i32>,
+    pub assignment_mode: AssignmentMode,
+    pub sharing: Option<Arc<str>>,
+    /// A form sent from the card that was invalid, empty otherwise.
+    pub form: FormState,
 }
 
 #[derive(Template)]
 #[template(path = "modules/upkeep/index.html")]
 pub struct IndexTemplate {
     pub due_items: Box<[PartItem]>,
+    pub waiting: Box<[PartItem]>,
+    pub backlog: Box<[PartItem]>,
+    pub pause_notice: Option<Box<str>>,
+    pub households: Box<[PartHousehold]>,
+    pub form: FormState,
+    pub session: Session,
+    pub authorized_session: AuthorizedSession,
+}
+
+/// The due and backlog columns on their own, with the backlog swapped out of band.
+#[derive(Template)]
+#[template(path = "modules/upkeep/columns.part.html")]
+pub struct ColumnsPartTemplate {
+    pub due_items: Box<[PartItem]>,
+    pub waiting: Box<[PartItem]>,
     pub backlog: Box<[PartItem]>,
+    pub households: Box<[PartHousehold]>,
+    pub oob: bool,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/item-card.part.html")]
+pub struct ItemCardPartTemplate {
+    pub item: PartItem,
+    pub households: Box<[PartHousehold]>,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/create-form.part.html")]
+pub struct CreateFormPartTemplate {
+    pub households: Box<[PartHousehold]>,
+    pub form: FormState,
+    pub authorized_session: AuthorizedSession,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/settings.html")]
+pub struct SettingsTemplate {
+    pub daily_item_limit: Option<i32>,
+    pub daily_minute_limit: Option<i32>,
+    pub pause_start: Option<NaiveDate>,
+    pub pause_end: Option<NaiveDate>,
+    pub is_paused: bool,
+    pub feed_token: Option<Arc<str>>,
+    pub session: Session,
+    pub authorized_session: AuthorizedSession,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/catch-up.html")]
+pub struct CatchUpTemplate {
+    pub days: usize,
+    pub daily_limit: usize,
+    pub is_paused: bool,
+    pub changes: Box<[PlannedChange]>,
+    pub encoded_changes: Box<str>,
+    pub session: Session,
+    pub authorized_session: AuthorizedSession,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/focus.html")]
+pub struct FocusTemplate {
+    pub item: Option<PartItem>,
+    pub skip: usize,
+    pub next_skip: usize,
+    pub is_paused: bool,
+    pub session: Session,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/forecast.html")]
+pub struct ForecastTemplate {
+    pub weeks: usize,
+    pub tolerance: i64,
+    pub days: Box<[ForecastDay]>,
+    pub level: bool,
+    pub changes: Box<[PlannedChange]>,
+    pub encoded_changes: Box<str>,
+    pub session: Session,
+    pub authorized_session: AuthorizedSession,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/households.html")]
+pub struct HouseholdsTemplate {
+    pub households: Box<[PartHousehold]>,
+    pub invites: Box<[PartHouseholdInvite]>,
+    pub history: Box<[PartHouseholdCompletion]>,
+    pub form: FormState,
+    /// The household whose invite form was sent with errors.
+    pub invite_household: Option<i32>,
+    pub invite_form: FormState,
+    pub session: Session,
+    pub authorized_session: AuthorizedSession,
+}
+
+impl HouseholdsTemplate {
+    fn is_invite_sent_to(&self, household_id: &i32) -> bool {
+        self.invite_household == Some(*household_id)
+    }
+}
+
+pub struct PartImportRow {
+    pub line: usize,
+    pub description: Box<str>,
+    pub is_new: bool,
+    pub is_duplicate: bool,
+    pub errors: Box<[Box<str>]>,
+    pub skipped: Option<Box<str>>,
+}
+
+/// Travels from the preview to the confirmation as JSON, so the summary can list everything that
+/// was left out.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PartSkippedItem {
+    pub description: Box<str>,
+    pub reason: Box<str>,
+}
+
+pub struct PartImportSummary {
+    pub imported: usize,
+    pub skipped: Box<[PartSkippedItem]>,
+}
+
+#[derive(Template)]
+#[template(path = "modules/upkeep/import.html")]
+pub struct ImportTemplate {
+    pub rows: Box<[PartImportRow]>,
+    pub new_count: usize,
+    pub encoded_items: Box<str>,
+    pub encoded_skipped: Box<str>,
+    pub notice: Option<Box<str>>,
+    pub summary: Option<PartImportSummary>,
     pub session: Session,
     pub authorized_session: AuthorizedSession,
 }
@@ -0,0 +1,276 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::database::{AssignmentMode, FetchUpkeepItem, ImportUpkeepItem, ResumePolicy};
+use super::migrate::{parse_taskwarrior, parse_todoist};
+
+/// One upkeep item as it is exported and imported. Only a description and cooldown are needed,
+/// so a spreadsheet with just those two columns can be imported as is.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TransferItem {
+    #[serde(default)]
+    pub id: Option<i32>,
+    pub description: String,
+    pub cooldown_days: i32,
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub paused_since: Option<NaiveDate>,
+    #[serde(default)]
+    pub resume_policy: Option<ResumePolicy>,
+    #[serde(default)]
+    pub effort_minutes: Option<i32>,
+    #[serde(default)]
+    pub window_days: Option<i32>,
+    #[serde(default)]
+    pub household_id: Option<i32>,
+    #[serde(default)]
+    pub assignment_mode: Option<AssignmentMode>,
+    #[serde(default)]
+    pub assigned_account_id: Option<i32>,
+    #[serde(default)]
+    pub revision: Option<i32>,
+}
+
+impl From<&FetchUpkeepItem> for TransferItem {
+    fn from(item: &FetchUpkeepItem) -> Self {
+        Self {
+            id: Some(item.id),
+            description: item.description.to_string(),
+            cooldown_days: item.cooldown_days,
+            due: Some(item.due),
+            paused_since: item.paused_since,
+            resume_policy: Some(item.resume_policy),
+            effort_minutes: item.effort_minutes,
+            window_days: Some(item.window_days),
+            household_id: item.household_id,
+            assignment_mode: Some(item.assignment_mode),
+            assigned_account_id: item.assigned_account_id,
+            revision: Some(item.revision),
+        }
+    }
+}
+
+pub fn export_json(items: &[FetchUpkeepItem]) -> Result<String> {
+    let items: Vec<TransferItem> = items.iter().map(TransferItem::from).collect();
+    Ok(serde_json::to_string_pretty(&items)?)
+}
+
+pub fn export_csv(items: &[FetchUpkeepItem]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for item in items.iter() {
+        writer.serialize(TransferItem::from(item))?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Where an uploaded file comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Reduce,
+    Taskwarrior,
+    Todoist,
+}
+
+impl Source {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reduce" => Some(Self::Reduce),
+            "taskwarrior" => Some(Self::Taskwarrior),
+            "todoist" => Some(Self::Todoist),
+            _ => None,
+        }
+    }
+}
+
+/// A row of an uploaded file, numbered the way a spreadsheet would show it.
+pub struct ParsedRow {
+    pub line: usize,
+    pub item: Result<TransferItem, String>,
+}
+
+/// A task that was read fine, but has no place in upkeep.
+pub struct SkippedTask {
+    pub line: usize,
+    pub description: String,
+    pub reason: String,
+}
+
+pub struct ParsedUpload {
+    pub rows: Box<[ParsedRow]>,
+    pub skipped: Box<[SkippedTask]>,
+}
+
+/// Fails as a whole only if the file can't be read at all, problems with single rows are
+/// reported per row.
+pub fn parse_upload(source: Source, data: &str) -> Result<ParsedUpload> {
+    match source {
+        Source::Reduce => Ok(ParsedUpload {
+            rows: parse_transfer(data)?,
+            skipped: Box::from([]),
+        }),
+        Source::Taskwarrior => parse_taskwarrior(data),
+        Source::Todoist => parse_todoist(data),
+    }
+}
+
+fn parse_transfer(data: &str) -> Result<Box<[ParsedRow]>> {
+    if data.trim_start().starts_with('[') {
+        let values: Vec<serde_json::Value> = serde_json::from_str(data)?;
+        return Ok(values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| ParsedRow {
+                line: index + 1,
+                item: serde_json::from_value(value).map_err(|error| error.to_string()),
+            })
+            .collect());
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    reader.headers()?;
+    Ok(reader
+        .deserialize()
+        .enumerate()
+        .map(|(index, item)| ParsedRow {
+            // The header is the first line.
+            line: index + 2,
+            item: item.map_err(|error| match error.kind() {
+                csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+                    Some(field) => format!("{} in column {}", err.kind(), field + 1),
+                    None => err.kind().to_string(),
+                },
+                _ => error.to_string(),
+            }),
+        })
+        .collect())
+}
+
+pub enum RowStatus {
+    New(ImportUpkeepItem),
+    Duplicate,
+    Invalid(Box<[Box<str>]>),
+}
+
+pub struct Membership {
+    pub household_id: i32,
+    pub account_id: i32,
+}
+
+fn normalize(description: &str) -> String {
+    description.trim().to_lowercase()
+}
+
+/// Checks each row and resolves defaults. Rows whose description matches an existing item, or an
+/// earlier row, are duplicates and won't be imported.
+pub fn check_rows(
+    rows: &[ParsedRow],
+    existing: &[FetchUpkeepItem],
+    account_id: i32,
+    memberships: &[Membership],
+    today: NaiveDate,
+) -> Box<[RowStatus]> {
+    let mut seen: HashSet<String> = existing
+        .iter()
+        .map(|item| normalize(&item.description))
+        .collect();
+    let is_member = |household_id: i32, account_id: i32| {
+        memberships.iter().any(|membership| {
+            membership.household_id == household_id && membership.account_id == account_id
+        })
+    };
+
+    rows.iter()
+        .map(|row| {
+            let item = match &row.item {
+                Ok(item) => item,
+                Err(error) => return RowStatus::Invalid(Box::from([error.as_str().into()])),
+            };
+
+            let mut errors: Vec<Box<str>> = Vec::new();
+            let description = item.description.trim();
+            if description.is_empty() {
+                errors.push("The description is empty".into());
+            } else if description.chars().count() > 255 {
+                errors.push("The description is longer than 255 characters".into());
+            }
+            if item.cooldown_days < 1 {
+                errors.push("The cooldown must be at least one day".into());
+            }
+            if item.effort_minutes.is_some_and(|effort| effort < 1) {
+                errors.push("The effort must be at least one minute".into());
+            }
+            if item.window_days.is_some_and(|window| window < 0) {
+                errors.push("The window can't be negative".into());
+            }
+            if let Some(household_id) = item.household_id {
+                if !is_member(household_id, account_id) {
+                    errors
+                        .push(format!("You are not a member of household {}", household_id).into());
+                }
+                if let Some(assigned) = item.assigned_account_id {
+                    if !is_member(household_id, assigned) {
+                        errors.push(
+                            format!(
+                                "Account {} is not a member of household {}",
+                                assigned, household_id
+                            )
+                            .into(),
+                        );
+                    }
+                }
+            }
+            if !errors.is_empty() {
+                return RowStatus::Invalid(errors.into());
+            }
+
+            if !seen.insert(normalize(description)) {
+                return RowStatus::Duplicate;
+            }
+
+            let assignment_mode = item.assignment_mode.unwrap_or(AssignmentMode::Rotate);
+            RowStatus::New(ImportUpkeepItem {
+                description: description.into(),
+                cooldown_days: item.cooldown_days,
+                due: item
+                    .due
+                    .unwrap_or(today + Duration::days(item.cooldown_days as i64)),
+                paused_since: item.paused_since,
+                resume_policy: item.resume_policy.unwrap_or(ResumePolicy::Shift),
+                effort_minutes: item.effort_minutes,
+                window_days: item.window_days.unwrap_or(0),
+                household_id: item.household_id,
+                assignment_mode,
+                assigned_account_id: match (item.household_id, assignment_mode) {
+                    (None, _) | (_, AssignmentMode::Completer) => None,
+                    (Some(_), AssignmentMode::Rotate) => {
+                        Some(item.assigned_account_id.unwrap_or(account_id))
+                    }
+                },
+            })
+        })
+        .collect()
+}
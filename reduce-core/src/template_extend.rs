@@ -1,8 +1,16 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
 
+use crate::flash::{Flash, FlashMessage};
+
 #[derive(Debug, Clone)]
 pub struct NavigationLink {
     pub href: Box<str>,
@@ -26,3 +34,41 @@ pub fn get_navigation_links() -> Arc<[NavigationLink]> {
         .map(|value| value.clone())
         .unwrap_or(Arc::from([]))
 }
+
+static DEV_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dev_mode(dev_mode: bool) {
+    DEV_MODE.store(dev_mode, Ordering::Relaxed);
+}
+
+/// Every page shows a warning then, so nobody mistakes it for a real server.
+pub fn is_dev_mode() -> bool {
+    DEV_MODE.load(Ordering::Relaxed)
+}
+
+tokio::task_local! {
+    static CSP_NONCE: Arc<str>;
+    static FLASH: Flash;
+}
+
+/// Runs a request with the nonce its pages put on inline scripts.
+pub async fn with_csp_nonce<F: Future>(nonce: Arc<str>, future: F) -> F::Output {
+    CSP_NONCE.scope(nonce, future).await
+}
+
+/// Empty outside of a request, the scripts won't run then, but nothing renders pages there.
+pub fn csp_nonce() -> Arc<str> {
+    CSP_NONCE
+        .try_with(|nonce| nonce.clone())
+        .unwrap_or_else(|_| Arc::from(""))
+}
+
+/// Runs a request whose pages show its flash messages. htmx requests don't, most of their page is
+/// thrown away, the messages are swapped in out of band there.
+pub async fn with_flash<F: Future>(flash: Flash, future: F) -> F::Output {
+    FLASH.scope(flash, future).await
+}
+
+pub fn take_flash_messages() -> Vec<FlashMessage> {
+    FLASH.try_with(|flash| flash.take()).unwrap_or_default()
+}
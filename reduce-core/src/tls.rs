@@ -0,0 +1,160 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Host, State},
+    http::Uri,
+    response::Redirect,
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::net::TcpListener;
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM, with the intermediate certificates after the certificate itself.
+    pub certificate_path: PathBuf,
+    pub key_path: PathBuf,
+    /// How often the files are checked for a renewed certificate. A SIGHUP reloads them right
+    /// away.
+    pub reload_interval: Duration,
+    /// Also listens for plain HTTP here, only to redirect to HTTPS.
+    pub redirect_bind_address: Option<Box<str>>,
+}
+
+impl TlsConfig {
+    pub fn new(certificate_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            certificate_path: certificate_path.into(),
+            key_path: key_path.into(),
+            reload_interval: Duration::from_secs(60),
+            redirect_bind_address: None,
+        }
+    }
+
+    pub async fn load(&self) -> Result<RustlsConfig> {
+        Ok(RustlsConfig::from_pem_file(&self.certificate_path, &self.key_path).await?)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// SIGHUP, the usual way to tell a server its certificate was renewed. Never fires on systems
+/// without signals.
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending().await
+    }
+}
+
+/// Swaps in renewed certificates while the server keeps running. Connections that are already
+/// open keep the certificate they started with. When the new files can't be loaded, the old
+/// certificate stays in use, a renewal tool may be halfway through writing them.
+pub fn watch_certificates(config: TlsConfig, rustls: RustlsConfig) {
+    tokio::spawn(async move {
+        let mut hangup = Hangup::new();
+        let mut interval = tokio::time::interval(config.reload_interval);
+        let files_modified = || {
+            (
+                modified(&config.certificate_path),
+                modified(&config.key_path),
+            )
+        };
+        let mut last_modified = files_modified();
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {}
+                _ = interval.tick() => {
+                    if files_modified() == last_modified {
+                        continue;
+                    }
+                }
+            }
+            last_modified = files_modified();
+
+            match rustls
+                .reload_from_pem_file(&config.certificate_path, &config.key_path)
+                .await
+            {
+                Ok(()) => tracing::info!("Reloaded the TLS certificate"),
+                Err(error) => tracing::error!("Could not reload the TLS certificate: {}", error),
+            }
+        }
+    });
+}
+
+async fn redirect_to_https(State(https_port): State<u16>, Host(host): Host, uri: Uri) -> Redirect {
+    // The host may come with the port of the plain listener.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+        _ => host,
+    };
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}{}", host, port, path))
+}
+
+/// Sends every request on plain HTTP to the same path on HTTPS, until `stopped` completes.
+pub async fn serve_redirect(
+    listener: TcpListener,
+    https_port: u16,
+    stopped: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let app = Router::new()
+        .fallback(redirect_to_https)
+        .with_state(https_port);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(stopped)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+/*
+* Reduce: Improve productivity by reducing complexity
+* Copyright (C) 2024  Damy Metzke
+*
+* This program is free software: you can redistribute it and/or modify
+* it under the terms of the GNU Affero General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU Affero General Public License for more details.
+*
+* You should have received a copy of the GNU Affero General Public License
+* along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Rule {
+    Required,
+    MaxLength(usize),
+    /// A whole number, both ends are included.
+    Range(i32, i32),
+    Email,
+}
+
+impl Rule {
+    /// Returns the message to show next to the field if the value breaks the rule.
+    fn check(self, value: &str) -> Option<Box<str>> {
+        match self {
+            Rule::Required if value.trim().is_empty() => Some("This field is required".into()),
+            Rule::Required => None,
+            Rule::MaxLength(max) if value.trim().chars().count() > max => {
+                Some(format!("Use at most {} characters", max).into())
+            }
+            Rule::MaxLength(_) => None,
+            Rule::Range(min, max) => match value.trim().parse::<i32>() {
+                Err(_) => Some("Enter a whole number".into()),
+                Ok(number) if number < min || number > max => {
+                    Some(format!("Enter a number from {} to {}", min, max).into())
+                }
+                Ok(_) => None,
+            },
+            Rule::Email if !is_email(value.trim()) => Some("Enter a valid email address".into()),
+            Rule::Email => None,
+        }
+    }
+}
+
+/// Deliberately loose, it only catches obvious typos. Whether the address exists is up to
+/// whoever uses it.
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !value.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+pub struct Field<'a> {
+    name: &'static str,
+    value: &'a Arc<str>,
+    rules: Vec<Rule>,
+}
+
+impl<'a> Field<'a> {
+    pub fn new(name: &'static str, value: &'a Arc<str>) -> Self {
+        Self {
+            name,
+            value,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn required(mut self) -> Self {
+        self.rules.push(Rule::Required);
+        self
+    }
+
+    pub fn max_length(mut self, max: usize) -> Self {
+        self.rules.push(Rule::MaxLength(max));
+        self
+    }
+
+    pub fn range(mut self, min: i32, max: i32) -> Self {
+        self.rules.push(Rule::Range(min, max));
+        self
+    }
+
+    pub fn email(mut self) -> Self {
+        self.rules.push(Rule::Email);
+        self
+    }
+
+    /// An empty field that isn't required is always valid.
+    fn check(&self) -> Option<Box<str>> {
+        let is_required = self.rules.iter().any(|rule| matches!(rule, Rule::Required));
+        if !is_required && self.value.trim().is_empty() {
+            return None;
+        }
+        self.rules.iter().find_map(|rule| rule.check(self.value))
+    }
+}
+
+/// Implemented by forms to declare the rules of their fields.
+pub trait Validate {
+    fn fields(&self) -> Box<[Field<'_>]>;
+
+    fn validate(&self) -> Result<(), FormState> {
+        let mut state = FormState::default();
+        for field in self.fields().iter() {
+            state.values.insert(field.name, field.value.clone());
+            if let Some(error) = field.check() {
+                state.errors.insert(field.name, error);
+            }
+        }
+
+        match state.errors.is_empty() {
+            true => Ok(()),
+            false => Err(state),
+        }
+    }
+}
+
+/// What was submitted and what was wrong with it, so a form can be shown again as it was sent.
+/// The default is an empty form without errors.
+#[derive(Clone, Debug, Default)]
+pub struct FormState {
+    values: HashMap<&'static str, Arc<str>>,
+    errors: HashMap<&'static str, Box<str>>,
+}
+
+impl FormState {
+    pub fn value(&self, name: &str) -> &str {
+        self.values.get(name).map_or("", |value| value)
+    }
+
+    pub fn error(&self, name: &str) -> Option<&str> {
+        self.errors.get(name).map(|error| &**error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Form {
+        name: Arc<str>,
+        email: Arc<str>,
+        count: Arc<str>,
+    }
+
+    impl Validate for Form {
+        fn fields(&self) -> Box<[Field<'_>]> {
+            Box::from([
+                Field::new("name", &self.name).required().max_length(5),
+                Field::new("email", &self.email).email(),
+                Field::new("count", &self.count).range(1, 10),
+            ])
+        }
+    }
+
+    fn form(name: &str, email: &str, count: &str) -> Form {
+        Form {
+            name: name.into(),
+            email: email.into(),
+            count: count.into(),
+        }
+    }
+
+    #[test]
+    fn emails_are_checked_loosely() {
+        for email in ["a@b.c", "first.last+tag@mail.example.org", " a@b.c "] {
+            assert!(is_email(email.trim()), "{}", email);
+        }
+        for email in ["", "a", "@b.c", "a@b", "a@.b", "a@b.", "a@b@c.d", "a b@c.d"] {
+            assert!(!is_email(email), "{}", email);
+        }
+    }
+
+    #[test]
+    fn rules_report_what_is_wrong() {
+        assert_eq!(
+            Rule::Required.check("  ").as_deref(),
+            Some("This field is required")
+        );
+        assert_eq!(
+            Rule::MaxLength(3).check("abcd").as_deref(),
+            Some("Use at most 3 characters")
+        );
+        assert_eq!(Rule::MaxLength(3).check(" äöü ").as_deref(), None);
+        assert_eq!(
+            Rule::Range(1, 10).check("1.5").as_deref(),
+            Some("Enter a whole number")
+        );
+        assert_eq!(
+            Rule::Range(1, 10).check("11").as_deref(),
+            Some("Enter a number from 1 to 10")
+        );
+        assert_eq!(Rule::Range(1, 10).check(" 10 ").as_deref(), None);
+        assert_eq!(
+            Rule::Email.check("nope").as_deref(),
+            Some("Enter a valid email address")
+        );
+    }
+
+    #[test]
+    fn valid_forms_pass() {
+        assert!(form("Bob", "bob@example.com", "3").validate().is_ok());
+        // Optional fields may be left empty.
+        assert!(form("Bob", "", " ").validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_forms_keep_their_values_and_errors() {
+        let state = form("", "bob", "0").validate().unwrap_err();
+
+        assert_eq!(state.error("name"), Some("This field is required"));
+        assert_eq!(state.error("email"), Some("Enter a valid email address"));
+        assert_eq!(state.error("count"), Some("Enter a number from 1 to 10"));
+        assert_eq!(state.value("email"), "bob");
+        assert_eq!(state.value("missing"), "");
+    }
+
+    #[test]
+    fn blank_values_count_as_missing() {
+        let state = form("      ", "", "").validate().unwrap_err();
+        assert_eq!(state.error("name"), Some("This field is required"));
+        assert_eq!(state.error("email"), None);
+    }
+}